@@ -20,9 +20,13 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use intel_mkl_tool::*;
-use std::str::FromStr;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 macro_rules! def_mkl_config {
     ($cfg:literal) => {
@@ -54,31 +58,529 @@ def_mkl_config!("mkl-dynamic-ilp64-seq");
 const MKL_CONFIG: &str = "mkl-static-ilp64-iomp";
 
 fn main() -> Result<()> {
+    // Read by intel-mkl-tool in OpenMpRuntime::candidates_from_env, not by env_var
+    println!("cargo:rerun-if-env-changed={}", ALLOW_LIBOMP_ENV);
+    if env_flag("INTEL_MKL_EXPLAIN") {
+        ExplainLogger::init();
+    }
     let cfg = Config::from_str(MKL_CONFIG).unwrap();
-    if let Ok(lib) = Library::new(cfg) {
-        lib.print_cargo_metadata()?;
+    if env::vars().any(|(key, _)| key.starts_with("CARGO_FEATURE_MKL_")) {
+        log::info!("{} is requested by cargo feature", cfg);
+    } else {
+        log::info!("{} is used since no mkl-*-*-* feature is set", cfg);
+    }
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    // Link a probe program to check the found MKL before using it
+    let verify_link = env_flag("INTEL_MKL_VERIFY_LINK");
+
+    // Use redistributable archives given explicitly instead of system MKL
+    if let Some(archives) = env_var("MKL_ARCHIVE") {
+        let lib = use_archives(cfg, &archives, &out_dir)?;
+        if verify_link {
+            lib.try_link(&out_dir)
+                .context("MKL in MKL_ARCHIVE cannot be linked")?;
+        }
+        emit(&lib, Source::Archive, &out_dir)?;
+        return Ok(());
+    }
+
+    // Alternative configs acceptable when the requested one is not installed
+    let fallbacks = match env_var("INTEL_MKL_FALLBACK") {
+        Some(fallbacks) => {
+            Fallback::parse_list(&fallbacks).context("Invalid INTEL_MKL_FALLBACK")?
+        }
+        None => Vec::new(),
+    };
+    println!("cargo:requested_config={}", cfg);
+    for candidate in cfg.fallbacks(&fallbacks) {
+        log::info!("Seek {} in system", candidate);
+        if let Some(lib) = seek_system(candidate, verify_link, &out_dir) {
+            if candidate != cfg {
+                warn(&format!(
+                    "Intel MKL ({}) is not found in system, and {} is used instead",
+                    cfg, candidate
+                ));
+            }
+            emit(&lib, Source::System, &out_dir)?;
+            return Ok(());
+        }
+    }
+    cfg.print_links_metadata();
+
+    // docs.rs does not allow network access, and does not need to link MKL
+    if env::var_os("DOCS_RS").is_some() {
+        log::info!("Skip fallback to container image on docs.rs");
+        print_rustc_env(cfg, Source::None, None, None);
         return Ok(());
     }
 
-    // Try ocipkg for static library.
+    // Try ocipkg.
     //
-    // This does not work for dynamic library because the directory
-    // where ocipkg download archive is not searched by ld
-    // unless user set `LD_LIBRARY_PATH` explictly.
-    if cfg.link == LinkType::Static {
-        if cfg!(target_os = "linux") {
-            let _ = ocipkg::link_package(&format!(
-                "ghcr.io/rust-math/rust-mkl/linux/{}:2020.1-3038006115",
-                MKL_CONFIG
-            ));
+    // For dynamic library, the shared libraries in the container are bundled
+    // into OUT_DIR since the ocipkg local storage is not searched by the loader.
+    if let Some(image) = OcipkgImage::from_env(cfg)? {
+        log::info!(
+            "{} is not found in system, and fall back to container image {}",
+            cfg,
+            image.name
+        );
+        check_lock(None)?;
+        return image.link(cfg).with_context(|| {
+            format!(
+                "Intel MKL ({}) is not found in system, and fallback to container image failed.\n\
+                 Install MKL and set MKLROOT, or configure the fallback by INTEL_MKL_OCIPKG_* environment variables.",
+                cfg
+            )
+        });
+    }
+    log::info!("Fallback to container image is disabled or unavailable for the target");
+    bail!(
+        "Intel MKL ({}) is not found in system. Install MKL and set MKLROOT.",
+        cfg
+    );
+}
+
+/// Where the linked MKL comes from, exposed as `intel_mkl_src::SOURCE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// Installed in system
+    System,
+    /// Unpacked from `MKL_ARCHIVE`
+    Archive,
+    /// Container image by ocipkg
+    Container,
+    /// Not linked, i.e. on docs.rs
+    None,
+}
+
+impl Source {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Source::System => "system",
+            Source::Archive => "archive",
+            Source::Container => "container",
+            Source::None => "none",
         }
-        if cfg!(target_os = "windows") {
-            let _ = ocipkg::link_package(&format!(
-                "ghcr.io/rust-math/rust-mkl/windows/{}:2022.0-3038006115",
-                MKL_CONFIG
-            ));
+    }
+}
+
+/// Pass the linked MKL to `src/lib.rs` as compile-time environment variables
+///
+/// Unknown values are empty strings since `env!` requires all of them.
+fn print_rustc_env(cfg: Config, source: Source, version: Option<&str>, library_dir: Option<&Path>) {
+    println!("cargo:rustc-env=INTEL_MKL_SRC_CONFIG={}", cfg);
+    println!("cargo:rustc-env=INTEL_MKL_SRC_SOURCE={}", source.as_str());
+    println!(
+        "cargo:rustc-env=INTEL_MKL_SRC_VERSION={}",
+        version.unwrap_or("")
+    );
+    println!(
+        "cargo:rustc-env=INTEL_MKL_SRC_LIBRARY_DIR={}",
+        library_dir.map_or(String::new(), |dir| dir.display().to_string())
+    );
+    println!(
+        "cargo:rustc-env=INTEL_MKL_SRC_DATA_MODEL={}",
+        cfg.index_size
+    );
+}
+
+/// Verify found MKL by the lockfile, print its metadata, and write CMake config and SBOM (with `sbom` feature) into `OUT_DIR`
+fn emit(lib: &Library, source: Source, out_dir: &Path) -> Result<()> {
+    log::info!(
+        "Use {} in {} (include: {})",
+        lib.config,
+        lib.library_dir.display(),
+        lib.include_dir.display()
+    );
+    check_lock(Some(lib))?;
+    lib.print_cargo_metadata()?;
+    lib.print_links_metadata();
+    let version = lib
+        .version()
+        .ok()
+        .map(|(year, minor, update)| format!("{}.{}.{}", year, minor, update));
+    print_rustc_env(
+        lib.config,
+        source,
+        version.as_deref(),
+        Some(&lib.library_dir),
+    );
+    // CMake projects built after this crate use the same MKL by `find_package(MKL CONFIG PATHS $DEP_MKL_CORE_CMAKE_DIR)`
+    let cmake_dir = out_dir.join("cmake");
+    write_cmake_config(std::slice::from_ref(lib), &cmake_dir)?;
+    println!("cargo:cmake_dir={}", cmake_dir.display());
+    #[cfg(feature = "sbom")]
+    {
+        let sbom = lib.write_sbom(out_dir)?;
+        println!("cargo:sbom={}", sbom.display());
+    }
+    Ok(())
+}
+
+/// Unpack MKL redistributable archives given by `MKL_ARCHIVE`
+#[cfg(feature = "archive")]
+fn use_archives(cfg: Config, archives: &str, out_dir: &Path) -> Result<Library> {
+    log::info!("Use MKL_ARCHIVE={} instead of system MKL", archives);
+    let archives: Vec<PathBuf> = env::split_paths(archives).collect();
+    for archive in &archives {
+        println!("cargo:rerun-if-changed={}", archive.display());
+    }
+    let sha256: Vec<String> = env_var("MKL_ARCHIVE_SHA256")
+        .map(|digests| digests.split(',').map(|d| d.trim().to_string()).collect())
+        .unwrap_or_default();
+    let dest = out_dir.join("mkl-archive");
+    Library::from_archives(cfg, &archives, &sha256, &dest)
+        .with_context(|| format!("Failed to use MKL_ARCHIVE for {}", cfg))
+}
+
+#[cfg(not(feature = "archive"))]
+fn use_archives(_cfg: Config, _archives: &str, _out_dir: &Path) -> Result<Library> {
+    bail!("MKL_ARCHIVE is set, but `archive` feature of intel-mkl-src is not enabled")
+}
+
+/// Compare found MKL with the lockfile given by `INTEL_MKL_LOCK`
+///
+/// `lib` is `None` if MKL in the container image is used, which cannot be verified.
+/// Mismatches fail the build, or are shown as warnings with `INTEL_MKL_LOCK_POLICY=warn`.
+#[cfg(feature = "lock")]
+fn check_lock(lib: Option<&Library>) -> Result<()> {
+    let path = match env_var(LOCK_ENV) {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(()),
+    };
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rerun-if-env-changed={}", LOCK_POLICY_ENV);
+    let policy = LockPolicy::from_env()?;
+    let lock = MklLock::read(&path)?;
+    let mismatches = match lib {
+        Some(lib) => lock.mismatches(lib)?,
+        None => vec!["MKL in container image is used, which is not locked".to_string()],
+    };
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    let message = format!(
+        "Intel MKL does not match the lockfile {}:\n{}\n\
+         Regenerate it by `intel-mkl-tool lock` if this change is intended",
+        path.display(),
+        mismatches.join("\n")
+    );
+    match policy {
+        LockPolicy::Deny => bail!(message),
+        LockPolicy::Warn => {
+            warn(&message);
+            Ok(())
         }
     }
+}
 
+#[cfg(not(feature = "lock"))]
+fn check_lock(_lib: Option<&Library>) -> Result<()> {
+    if env_var("INTEL_MKL_LOCK").is_some() {
+        bail!("INTEL_MKL_LOCK is set, but `lock` feature of intel-mkl-src is not enabled");
+    }
     Ok(())
 }
+
+/// Seek MKL installed in system
+///
+/// If `verify_link` is true, every MKL found is tried until one of them is linked successfully.
+fn seek_system(cfg: Config, verify_link: bool, out_dir: &Path) -> Option<Library> {
+    if !verify_link {
+        return Library::new(cfg).ok();
+    }
+    for lib in Discovery::with_defaults(cfg).mode(Mode::CollectAll).run() {
+        match lib.try_link(out_dir) {
+            Ok(()) => return Some(lib),
+            Err(e) => warn(&format!(
+                "Skip MKL in {}: {:#}",
+                lib.library_dir.display(),
+                e
+            )),
+        }
+    }
+    None
+}
+
+/// Read environment variable, and let cargo rerun `build.rs` when it is changed
+fn env_var(key: &str) -> Option<String> {
+    println!("cargo:rerun-if-env-changed={}", key);
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// Environment variable set to `1`, `true`, or `yes`
+fn env_flag(key: &str) -> bool {
+    env_var(key).map_or(false, |value| {
+        matches!(value.to_lowercase().as_str(), "1" | "true" | "yes")
+    })
+}
+
+/// Show a message as `cargo:warning`, which must be a single line
+fn warn(message: &str) {
+    for line in message.lines() {
+        println!("cargo:warning={}", line);
+    }
+}
+
+/// Route `log` records of `intel-mkl-tool` and this script to `cargo:warning`, enabled by `INTEL_MKL_EXPLAIN`
+///
+/// Cargo hides the output of build scripts unless it fails, but shows warnings.
+struct ExplainLogger;
+
+static EXPLAIN_LOGGER: ExplainLogger = ExplainLogger;
+
+impl ExplainLogger {
+    fn init() {
+        if log::set_logger(&EXPLAIN_LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Info);
+        }
+    }
+}
+
+impl log::Log for ExplainLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            warn(&format!("[explain] {}", record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// How to get container image, set by `INTEL_MKL_OCIPKG_POLICY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OcipkgPolicy {
+    /// Download from registry if the image does not exist in local storage (default)
+    Download,
+    /// Use ocipkg local storage or `INTEL_MKL_OCIPKG_ARCHIVE`, never access to network
+    Offline,
+    /// Do not use container image
+    Never,
+}
+
+impl FromStr for OcipkgPolicy {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "download" => OcipkgPolicy::Download,
+            "offline" => OcipkgPolicy::Offline,
+            "never" => OcipkgPolicy::Never,
+            another => bail!(
+                "Invalid INTEL_MKL_OCIPKG_POLICY: {} (expected download, offline, or never)",
+                another
+            ),
+        })
+    }
+}
+
+/// Container image of MKL used when MKL is not found in system
+///
+/// | Environment variable          | Default                                  |
+/// |:------------------------------|:-----------------------------------------|
+/// | `INTEL_MKL_OCIPKG_REGISTRY`   | `ghcr.io`                                |
+/// | `INTEL_MKL_OCIPKG_IMAGE`      | `rust-math/rust-mkl/{os}/{config}`       |
+/// | `INTEL_MKL_OCIPKG_TAG`        | `2020.1-3038006115` for Linux, `2022.0-3038006115` for Windows |
+/// | `INTEL_MKL_OCIPKG_ARCHIVE`    | (none) oci-archive file loaded into local storage instead of downloading |
+/// | `INTEL_MKL_OCIPKG_POLICY`     | `download`, `offline`, or `never`        |
+///
+/// `{os}` and `{config}` in `INTEL_MKL_OCIPKG_IMAGE` are replaced by
+/// the target OS (`linux` or `windows`) and MKL config, e.g. `mkl-static-lp64-seq`.
+#[derive(Debug)]
+struct OcipkgImage {
+    name: String,
+    archive: Option<PathBuf>,
+    policy: OcipkgPolicy,
+}
+
+impl OcipkgImage {
+    /// Returns `None` if the fallback is disabled or no image is available for the target
+    fn from_env(cfg: Config) -> Result<Option<Self>> {
+        let policy = match env_var("INTEL_MKL_OCIPKG_POLICY") {
+            Some(policy) => OcipkgPolicy::from_str(&policy)?,
+            None => OcipkgPolicy::Download,
+        };
+        if policy == OcipkgPolicy::Never {
+            return Ok(None);
+        }
+        let os = env::var("CARGO_CFG_TARGET_OS")?;
+        let default_tag = match os.as_str() {
+            "linux" => Some("2020.1-3038006115"),
+            "windows" => Some("2022.0-3038006115"),
+            _ => None,
+        };
+        let registry = env_var("INTEL_MKL_OCIPKG_REGISTRY").unwrap_or_else(|| "ghcr.io".into());
+        let image = env_var("INTEL_MKL_OCIPKG_IMAGE")
+            .unwrap_or_else(|| "rust-math/rust-mkl/{os}/{config}".into())
+            .replace("{os}", &os)
+            .replace("{config}", &cfg.to_string());
+        let tag = match (env_var("INTEL_MKL_OCIPKG_TAG"), default_tag) {
+            (Some(tag), _) => tag,
+            (None, Some(tag)) => tag.to_string(),
+            (None, None) => return Ok(None),
+        };
+        let archive = env_var("INTEL_MKL_OCIPKG_ARCHIVE").map(PathBuf::from);
+        Ok(Some(OcipkgImage {
+            name: format!("{}/{}:{}", registry, image, tag),
+            archive,
+            policy,
+        }))
+    }
+
+    /// Get image into ocipkg local storage, and print `cargo:rustc-link-*` and links metadata
+    fn link(&self, cfg: Config) -> Result<()> {
+        let name = match &self.archive {
+            Some(archive) => load_archive(archive, &self.name)?,
+            None => ocipkg::ImageName::parse(&self.name)?,
+        };
+        let dir = ocipkg::local::image_dir(&name)?;
+        if !dir.exists() {
+            if self.policy == OcipkgPolicy::Offline {
+                bail!(
+                    "Container image {} does not exist in {}, and download is forbidden by INTEL_MKL_OCIPKG_POLICY=offline",
+                    name,
+                    dir.display()
+                );
+            }
+            ocipkg::distribution::get_image(&name)
+                .with_context(|| format!("Failed to get container image {}", name))?;
+        }
+        let lib_dir = match cfg.link {
+            LinkType::Static => {
+                ocipkg::link_package(&name.to_string())?;
+                dir
+            }
+            LinkType::Dynamic => bundle_dynamic_libraries(cfg, &dir)?,
+        };
+        println!("cargo:lib={}", lib_dir.display());
+        // Tag is `{MKL version}-{build id}`, e.g. `2020.1-3038006115`
+        let version = name
+            .reference
+            .as_str()
+            .split_once('-')
+            .map(|(version, _)| version.to_string());
+        if let Some(version) = &version {
+            println!("cargo:version={}", version);
+        }
+        print_rustc_env(cfg, Source::Container, version.as_deref(), Some(&lib_dir));
+        Ok(())
+    }
+}
+
+/// Copy libraries in the container into `$OUT_DIR/lib`, and link them dynamically.
+///
+/// Cargo adds `$OUT_DIR/lib` to `LD_LIBRARY_PATH` (or `PATH` on Windows) for `cargo run` and `cargo test`.
+/// Other executables do not find them, and thus the directory is exported as `DEP_MKL_CORE_BUNDLE_DIR`
+/// for the final crate to set its rpath or to copy the libraries next to its executables.
+///
+/// CPU-dispatch libraries listed in [mkl_dyn_libs] are not linked, but bundled since MKL loads them at runtime.
+/// OpenMP runtime is linked in the runtime and link type chosen by `INTEL_MKL_OPENMP_LINK` and `INTEL_MKL_ALLOW_LIBOMP`.
+fn bundle_dynamic_libraries(cfg: Config, image_dir: &Path) -> Result<PathBuf> {
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let lib_dir = out_dir.join("lib");
+    fs::create_dir_all(&lib_dir)?;
+
+    for entry in fs::read_dir(image_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let file_name = path.file_name().expect("read_dir never returns `..`");
+        link_or_copy(&path, &lib_dir.join(file_name))?;
+    }
+
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    for lib in mkl_libs(cfg) {
+        println!("cargo:rustc-link-lib=dylib={}", lib);
+    }
+    if cfg.parallel == Threading::OpenMP {
+        let policy = match env_var(OPENMP_LINK_POLICY_ENV) {
+            Some(policy) => OpenMpLinkPolicy::from_str(&policy)?,
+            None => OpenMpLinkPolicy::default(),
+        };
+        let (runtime, link) = policy
+            .link_types(cfg)
+            .into_iter()
+            .flat_map(|link| {
+                OpenMpRuntime::candidates_from_env()
+                    .into_iter()
+                    .map(move |runtime| (runtime, link))
+            })
+            .find(|(runtime, link)| lib_dir.join(runtime.file_name(*link)).is_file())
+            .with_context(|| {
+                format!(
+                    "OpenMP runtime allowed by {}={} is not found in the container image",
+                    OPENMP_LINK_POLICY_ENV, policy
+                )
+            })?;
+        let kind = match link {
+            LinkType::Static => "static",
+            LinkType::Dynamic => "dylib",
+        };
+        println!("cargo:rustc-link-lib={}={}", kind, runtime.lib_name());
+    }
+    println!("cargo:bundle_dir={}", lib_dir.display());
+
+    // Windows DLLs have different names from the import libraries, e.g. `mkl_def.2.dll`
+    if env::var("CARGO_CFG_TARGET_OS")? != "windows" {
+        for lib in mkl_dyn_libs(cfg) {
+            let file_name = mkl_file_name(LinkType::Dynamic, &lib);
+            let found = fs::read_dir(&lib_dir)?.flatten().any(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |name| name.starts_with(&file_name))
+            });
+            if !found {
+                println!(
+                    "cargo:warning=CPU-dispatch library {} is not found in the container image",
+                    file_name
+                );
+            }
+        }
+    }
+    Ok(lib_dir)
+}
+
+/// Hard link to save disk space since MKL libraries are large, or copy if it fails,
+/// e.g. across filesystems.
+fn link_or_copy(src: &Path, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        fs::remove_file(dest)?;
+    }
+    if fs::hard_link(src, dest).is_err() {
+        fs::copy(src, dest)
+            .with_context(|| format!("Failed to copy {} to {}", src.display(), dest.display()))?;
+    }
+    Ok(())
+}
+
+/// Load oci-archive into ocipkg local storage, and returns the name of image to be linked
+///
+/// The image named `expected` is used if it exists in the archive,
+/// or the first one is used.
+fn load_archive(archive: &Path, expected: &str) -> Result<ocipkg::ImageName> {
+    println!("cargo:rerun-if-changed={}", archive.display());
+    let mut f = fs::File::open(archive).with_context(|| {
+        format!(
+            "Failed to open INTEL_MKL_OCIPKG_ARCHIVE={}",
+            archive.display()
+        )
+    })?;
+    let names: Vec<_> = ocipkg::image::Archive::new(&mut f)
+        .get_manifests()?
+        .into_iter()
+        .map(|(name, _manifest)| name)
+        .collect();
+    let name = names
+        .iter()
+        .find(|name| name.to_string() == expected)
+        .or_else(|| names.first())
+        .cloned()
+        .with_context(|| format!("No image in {}", archive.display()))?;
+    ocipkg::image::load(archive)?;
+    Ok(name)
+}
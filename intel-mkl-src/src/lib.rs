@@ -19,4 +19,40 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
+
+//! Redistribution of Intel(R) MKL as a crate
+//!
+//! This crate links MKL, and exposes which MKL is linked:
+//!
+//! ```no_run
+//! println!(
+//!     "Intel MKL {} ({}) from {} in {}",
+//!     intel_mkl_src::VERSION,
+//!     intel_mkl_src::CONFIG,
+//!     intel_mkl_src::SOURCE,
+//!     intel_mkl_src::LIBRARY_DIR
+//! );
+//! ```
 #![no_std]
+
+/// Linked MKL config, e.g. `mkl-static-lp64-seq`
+///
+/// This may differ from the feature when `INTEL_MKL_FALLBACK` is set.
+pub const CONFIG: &str = env!("INTEL_MKL_SRC_CONFIG");
+
+/// Version of linked MKL, e.g. `2020.0.1`, or empty if unknown
+pub const VERSION: &str = env!("INTEL_MKL_SRC_VERSION");
+
+/// Where the linked MKL comes from
+///
+/// - `system`: Installed in system
+/// - `archive`: Unpacked from `MKL_ARCHIVE`
+/// - `container`: Container image downloaded by ocipkg
+/// - `none`: MKL is not linked, e.g. on docs.rs
+pub const SOURCE: &str = env!("INTEL_MKL_SRC_SOURCE");
+
+/// Directory of linked MKL libraries, or empty if MKL is not linked
+pub const LIBRARY_DIR: &str = env!("INTEL_MKL_SRC_LIBRARY_DIR");
+
+/// Data model of linked MKL, `lp64` or `ilp64`
+pub const DATA_MODEL: &str = env!("INTEL_MKL_SRC_DATA_MODEL");
@@ -0,0 +1,179 @@
+//! Runtime diagnostics of the current process
+//!
+//! Loading several OpenMP runtimes into one process, e.g. `libiomp5` for MKL and `libgomp` for another dependency,
+//! causes hangs or wrong number of threads. [openmp_runtimes] lists OpenMP runtimes loaded in the current process,
+//! and [check_openmp_runtimes] returns the conflict as an error message. Nothing is printed by this module,
+//! and the caller decides how to report it, e.g. in tests or a startup check:
+//!
+//! ```no_run
+//! if let Err(conflict) = intel_mkl_sys::diagnostics::check_openmp_runtimes() {
+//!     eprintln!("warning: {}", conflict);
+//! }
+//! ```
+//!
+//! Only shared libraries are detected. OpenMP runtime linked statically into the executable is not listed.
+
+use std::{fmt, fs, path::PathBuf};
+
+/// Kind of OpenMP runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpenMpRuntimeKind {
+    /// Intel OpenMP runtime, `libiomp5`
+    Intel,
+    /// LLVM OpenMP runtime, `libomp`
+    Llvm,
+    /// GNU OpenMP runtime, `libgomp`
+    Gnu,
+}
+
+impl fmt::Display for OpenMpRuntimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenMpRuntimeKind::Intel => write!(f, "Intel OpenMP (libiomp5)"),
+            OpenMpRuntimeKind::Llvm => write!(f, "LLVM OpenMP (libomp)"),
+            OpenMpRuntimeKind::Gnu => write!(f, "GNU OpenMP (libgomp)"),
+        }
+    }
+}
+
+impl OpenMpRuntimeKind {
+    /// Guess from a file name of shared library, e.g. `libgomp.so.1`
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let stem = file_name.split('.').next()?;
+        match stem {
+            "libiomp5" | "libiomp5md" => Some(OpenMpRuntimeKind::Intel),
+            "libomp" | "libomp5" => Some(OpenMpRuntimeKind::Llvm),
+            "libgomp" => Some(OpenMpRuntimeKind::Gnu),
+            _ => None,
+        }
+    }
+}
+
+/// OpenMP runtime loaded in the current process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedOpenMpRuntime {
+    pub kind: OpenMpRuntimeKind,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for LoadedOpenMpRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.path.display())
+    }
+}
+
+/// List OpenMP runtimes loaded in the current process
+///
+/// This reads `/proc/self/maps` on Linux, and returns an empty list on other platforms or if it is not readable.
+pub fn openmp_runtimes() -> Vec<LoadedOpenMpRuntime> {
+    if cfg!(target_os = "linux") {
+        fs::read_to_string("/proc/self/maps")
+            .map(|maps| parse_maps(&maps))
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Check only one OpenMP runtime is loaded in the current process
+///
+/// Returns an error message listing all runtimes if more than one is loaded, see [openmp_runtimes].
+pub fn check_openmp_runtimes() -> Result<(), String> {
+    check_runtimes(&openmp_runtimes())
+}
+
+fn check_runtimes(runtimes: &[LoadedOpenMpRuntime]) -> Result<(), String> {
+    if runtimes.len() > 1 {
+        return Err(conflict_message(runtimes));
+    }
+    Ok(())
+}
+
+fn conflict_message(runtimes: &[LoadedOpenMpRuntime]) -> String {
+    let list: Vec<String> = runtimes.iter().map(|rt| rt.to_string()).collect();
+    format!(
+        "Multiple OpenMP runtimes are loaded, which may cause hangs or wrong number of threads: {}",
+        list.join(", ")
+    )
+}
+
+/// Collect OpenMP runtimes from the contents of `/proc/{pid}/maps`
+fn parse_maps(maps: &str) -> Vec<LoadedOpenMpRuntime> {
+    let mut runtimes: Vec<LoadedOpenMpRuntime> = Vec::new();
+    for line in maps.lines() {
+        // `address perms offset dev inode pathname`, and only pathname contains `/`
+        let path = match line.find('/') {
+            Some(start) => PathBuf::from(line[start..].trim_end()),
+            None => continue,
+        };
+        let kind = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(OpenMpRuntimeKind::from_file_name)
+        {
+            Some(kind) => kind,
+            None => continue,
+        };
+        // A shared library is mapped into several regions
+        if runtimes.iter().all(|rt| rt.path != path) {
+            runtimes.push(LoadedOpenMpRuntime { kind, path });
+        }
+    }
+    runtimes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let maps = "\
+55d0c0a00000-55d0c0a02000 r--p 00000000 08:01 1234 /usr/bin/app
+7f1c00000000-7f1c00100000 r--p 00000000 08:01 2345 /opt/intel/lib/intel64/libiomp5.so
+7f1c00100000-7f1c00200000 r-xp 00100000 08:01 2345 /opt/intel/lib/intel64/libiomp5.so
+7f1c00300000-7f1c00400000 r-xp 00000000 08:01 3456 /usr/lib/x86_64-linux-gnu/libgomp.so.1.0.0
+7f1c00500000-7f1c00600000 rw-p 00000000 00:00 0 [heap]
+7f1c00600000-7f1c00700000 r-xp 00000000 08:01 4567 /usr/lib/libgompx.so
+";
+        let runtimes = parse_maps(maps);
+        assert_eq!(
+            runtimes,
+            vec![
+                LoadedOpenMpRuntime {
+                    kind: OpenMpRuntimeKind::Intel,
+                    path: PathBuf::from("/opt/intel/lib/intel64/libiomp5.so"),
+                },
+                LoadedOpenMpRuntime {
+                    kind: OpenMpRuntimeKind::Gnu,
+                    path: PathBuf::from("/usr/lib/x86_64-linux-gnu/libgomp.so.1.0.0"),
+                },
+            ]
+        );
+        assert!(conflict_message(&runtimes).contains("libgomp.so.1.0.0"));
+    }
+
+    #[test]
+    fn check() {
+        let single = "\
+7f1c00000000-7f1c00100000 r--p 00000000 08:01 2345 /opt/intel/lib/intel64/libiomp5.so
+7f1c00100000-7f1c00200000 r-xp 00100000 08:01 2345 /opt/intel/lib/intel64/libiomp5.so
+7f1c00300000-7f1c00400000 r-xp 00000000 08:01 3456 /usr/lib/x86_64-linux-gnu/libm.so.6
+";
+        assert_eq!(check_runtimes(&parse_maps(single)), Ok(()));
+
+        let conflict = "\
+7f1c00000000-7f1c00100000 r-xp 00000000 08:01 2345 /opt/intel/lib/intel64/libiomp5.so
+7f1c00300000-7f1c00400000 r-xp 00000000 08:01 3456 /usr/lib/llvm-14/lib/libomp.so.5
+";
+        assert_eq!(
+            check_runtimes(&parse_maps(conflict)),
+            Err(
+                "Multiple OpenMP runtimes are loaded, which may cause hangs or wrong number of threads: \
+                 Intel OpenMP (libiomp5) at /opt/intel/lib/intel64/libiomp5.so, \
+                 LLVM OpenMP (libomp) at /usr/lib/llvm-14/lib/libomp.so.5"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -10,15 +10,21 @@
 //! - [lapacke-sys](https://crates.io/crates/lapacke-sys)
 //! - [fftw-sys](https://crates.io/crates/fftw-sys)
 //!
+//! [diagnostics] inspects the current process, e.g. conflicting OpenMP runtimes.
+//!
 #![allow(
     improper_ctypes,
     non_upper_case_globals,
     non_camel_case_types,
-    non_snake_case
+    non_snake_case,
+    unknown_lints,
+    unpredictable_function_pointer_comparisons
 )]
 
 extern crate intel_mkl_src;
 
+pub mod diagnostics;
+
 include!("mkl.rs");
 
 // Test linking
@@ -52,7 +58,7 @@ mod tests {
                 b[i],
                 a[i].cos(),
                 max_ulps = 4,
-                epsilon = std::f64::EPSILON
+                epsilon = f64::EPSILON
             ));
         }
     }
@@ -0,0 +1,123 @@
+//! Seek MKL in a synthetic large directory tree
+//!
+//! The tree consists of many unrelated files, ignored directories like `documentation/`,
+//! and a MKL installation placed at the end, or at the beginning to measure that
+//! the default options stop walking when MKL is found.
+
+#[macro_use]
+extern crate criterion;
+
+use criterion::Criterion;
+use intel_mkl_tool::*;
+use std::{fs, path::Path, str::FromStr};
+
+fn touch(path: &Path, contents: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn synthetic_tree(root: &Path, mkl_dir: &str) {
+    if root.exists() {
+        fs::remove_dir_all(root).unwrap();
+    }
+    for pkg in 0..50 {
+        for sub in 0..10 {
+            for file in 0..20 {
+                touch(
+                    &root.join(format!("pkg{}/sub{}/file{}.txt", pkg, sub, file)),
+                    b"",
+                );
+            }
+        }
+    }
+    for dir in &["documentation", "examples", "benchmarks", "lib/ia32"] {
+        for file in 0..2000 {
+            touch(&root.join(format!("{}/file{}.txt", dir, file)), b"");
+        }
+    }
+
+    let mkl = root.join(mkl_dir);
+    touch(&mkl.join("include/mkl.h"), b"");
+    touch(
+        &mkl.join("include/mkl_version.h"),
+        b"#define __INTEL_MKL__ 2020\n#define __INTEL_MKL_MINOR__ 0\n#define __INTEL_MKL_UPDATE__ 1\n",
+    );
+    for name in &["mkl_intel_lp64", "mkl_sequential", "mkl_core"] {
+        touch(
+            &mkl.join("lib/intel64")
+                .join(mkl_file_name(LinkType::Static, name)),
+            b"!<arch>\n",
+        );
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let root = std::env::temp_dir().join("intel-mkl-tool-bench-seek");
+    synthetic_tree(&root, "pkg_z/mkl");
+    let early = std::env::temp_dir().join("intel-mkl-tool-bench-seek-early");
+    synthetic_tree(&early, "a/mkl");
+    let cfg = Config::from_str("mkl-static-lp64-seq").unwrap();
+
+    c.bench_function("seek_default", |b| {
+        b.iter(|| {
+            Library::seek_directory(cfg, &root).unwrap().unwrap();
+        })
+    });
+    c.bench_function("seek_no_ignore", |b| {
+        let opt = SeekOptions {
+            ignore_patterns: Vec::new(),
+            ..SeekOptions::default()
+        };
+        b.iter(|| {
+            Library::seek_directory_with(cfg, &root, &opt)
+                .unwrap()
+                .unwrap();
+        })
+    });
+    // Rank all candidates in the whole tree, opt-in since it walks everything
+    c.bench_function("seek_rank_all", |b| {
+        let opt = SeekOptions::default().first_match(false);
+        b.iter(|| {
+            Library::seek_directory_with(cfg, &root, &opt)
+                .unwrap()
+                .unwrap();
+        })
+    });
+    c.bench_function("seek_default_early", |b| {
+        b.iter(|| {
+            Library::seek_directory(cfg, &early).unwrap().unwrap();
+        })
+    });
+    c.bench_function("seek_rank_all_early", |b| {
+        let opt = SeekOptions::default().first_match(false);
+        b.iter(|| {
+            Library::seek_directory_with(cfg, &early, &opt)
+                .unwrap()
+                .unwrap();
+        })
+    });
+    c.bench_function("seek_max_depth", |b| {
+        let opt = SeekOptions::default().max_depth(5);
+        b.iter(|| {
+            Library::seek_directory_with(cfg, &root, &opt)
+                .unwrap()
+                .unwrap();
+        })
+    });
+    if cfg!(feature = "parallel") {
+        c.bench_function("seek_parallel", |b| {
+            let opt = SeekOptions::default().parallel(true);
+            b.iter(|| {
+                Library::seek_directory_with(cfg, &root, &opt)
+                    .unwrap()
+                    .unwrap();
+            })
+        });
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_dir_all(&early).unwrap();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,216 @@
+//! Unpack MKL redistributable archives, enabled by `archive` feature
+
+use crate::{sha256_file, Config, Library};
+use anyhow::{bail, Context, Result};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the file recording SHA-256 digest of the unpacked archive
+const STAMP_FILE: &str = ".mkl-archive-sha256";
+
+/// Unpack an archive into `dest` directory
+///
+/// Supported formats are determined by the extension:
+///
+/// - `*.tar`
+/// - `*.tar.gz` or `*.tgz`
+/// - `*.zip`, and zip based formats, i.e. Python wheel `*.whl` and NuGet package `*.nupkg`
+///
+/// If `sha256` is given, the digest of the archive is verified before unpacking.
+/// Unpacking is skipped when `dest` already contains the same archive.
+pub fn unpack_archive(archive: &Path, dest: &Path, sha256: Option<&str>) -> Result<()> {
+    let digest = sha256_file(archive)?;
+    if let Some(expected) = sha256 {
+        if !digest.eq_ignore_ascii_case(expected.trim()) {
+            bail!(
+                "SHA-256 mismatch of {}: expected {}, actual {}",
+                archive.display(),
+                expected.trim(),
+                digest
+            );
+        }
+    }
+
+    let stamp = dest.join(STAMP_FILE);
+    if fs::read_to_string(&stamp).map_or(false, |s| s == digest) {
+        log::info!(
+            "{} is already unpacked in {}",
+            archive.display(),
+            dest.display()
+        );
+        return Ok(());
+    }
+    if dest.exists() {
+        fs::remove_dir_all(dest)?;
+    }
+    fs::create_dir_all(dest)?;
+
+    let name = archive
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Invalid archive name: {}", archive.display()))?
+        .to_lowercase();
+    let f = io::BufReader::new(fs::File::open(archive)?);
+    log::info!("Unpack {} into {}", archive.display(), dest.display());
+    if name.ends_with(".tar") {
+        tar::Archive::new(f).unpack(dest)?;
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar::Archive::new(flate2::read::GzDecoder::new(f)).unpack(dest)?;
+    } else if name.ends_with(".zip") || name.ends_with(".whl") || name.ends_with(".nupkg") {
+        zip::ZipArchive::new(f)?.extract(dest)?;
+    } else {
+        bail!("Unsupported archive format: {}", archive.display());
+    }
+    fs::write(stamp, digest)?;
+    Ok(())
+}
+
+impl Library {
+    /// Unpack archives into `dest`, and seek MKL in it by [Library::seek_directory]
+    ///
+    /// Several archives can be used together since some redistributions
+    /// split headers and libraries, e.g. `mkl-include` and `mkl-static` wheels in PyPI.
+    /// Each archive is unpacked into a sub-directory of `dest` named by its file name.
+    ///
+    /// `sha256` is empty or the list of expected digests in the same order as `archives`.
+    pub fn from_archives(
+        config: Config,
+        archives: &[PathBuf],
+        sha256: &[String],
+        dest: &Path,
+    ) -> Result<Self> {
+        if !sha256.is_empty() && sha256.len() != archives.len() {
+            bail!(
+                "{} SHA-256 digests are given for {} archives",
+                sha256.len(),
+                archives.len()
+            );
+        }
+        for (i, archive) in archives.iter().enumerate() {
+            let name = archive
+                .file_name()
+                .with_context(|| format!("Invalid archive path: {}", archive.display()))?;
+            unpack_archive(archive, &dest.join(name), sha256.get(i).map(|s| s.as_str()))?;
+        }
+        match Self::seek_directory(config, dest)? {
+            Some(lib) => Ok(lib),
+            None => bail!("{} is not found in archives", config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_os = "linux")]
+    use crate::testing::*;
+    #[cfg(target_os = "linux")]
+    use std::str::FromStr;
+
+    /// Pack `dir` in `root` into `archive` as `tar`, `tar.gz` or zip based format
+    #[cfg(target_os = "linux")]
+    fn pack(root: &Path, dir: &str, archive: &Path) -> Result<()> {
+        let name = archive.to_str().unwrap();
+        if name.ends_with(".tar") {
+            let mut tar = tar::Builder::new(fs::File::create(archive)?);
+            tar.append_dir_all(dir, root.join(dir))?;
+            tar.finish()?;
+        } else if name.ends_with(".tar.gz") {
+            let gz = flate2::write::GzEncoder::new(
+                fs::File::create(archive)?,
+                flate2::Compression::default(),
+            );
+            let mut tar = tar::Builder::new(gz);
+            tar.append_dir_all(dir, root.join(dir))?;
+            tar.into_inner()?.finish()?;
+        } else {
+            let mut zip = zip::ZipWriter::new(fs::File::create(archive)?);
+            for entry in walkdir::WalkDir::new(root.join(dir)) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let name = entry.path().strip_prefix(root)?.to_str().unwrap();
+                zip.start_file(name.replace('\\', "/"), Default::default())?;
+                io::copy(&mut fs::File::open(entry.path())?, &mut zip)?;
+            }
+            zip.finish()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_archives() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::PipWheel, (2023, 1, 0))?;
+        let archives = mkl.root().join("archives");
+        fs::create_dir_all(&archives)?;
+        let include = archives.join("mkl_include.tar");
+        pack(mkl.root(), "mkl_include-2023.1.0.data", &include)?;
+        let tgz = archives.join("mkl_devel.tar.gz");
+        pack(mkl.root(), "mkl_devel-2023.1.0.data", &tgz)?;
+        let whl = archives.join("mkl_devel-2023.1.0-py2.py3-none-manylinux1_x86_64.whl");
+        pack(mkl.root(), "mkl_devel-2023.1.0.data", &whl)?;
+
+        let cfg = Config::from_str("mkl-static-lp64-iomp")?;
+        for devel in [&tgz, &whl] {
+            let dest = mkl.root().join("unpacked");
+            let lib = Library::from_archives(cfg, &[include.clone(), devel.clone()], &[], &dest)?;
+            assert_eq!(
+                lib.include_dir,
+                dest.join("mkl_include.tar/mkl_include-2023.1.0.data/data/include")
+            );
+            assert_eq!(
+                lib.library_dir,
+                dest.join(devel.file_name().unwrap())
+                    .join("mkl_devel-2023.1.0.data/data/lib")
+            );
+            assert_eq!(lib.iomp5_static_dir, Some(lib.library_dir.clone()));
+            assert_eq!(lib.version()?, (2023, 1, 0));
+            fs::remove_dir_all(&dest)?;
+        }
+
+        // Unpacked archive is reused by the stamp file
+        let dest = mkl.root().join("unpacked");
+        let digests = vec![sha256_file(&include)?, sha256_file(&tgz)?];
+        let archives = [include.clone(), tgz.clone()];
+        Library::from_archives(cfg, &archives, &digests, &dest)?;
+        let marker = dest.join("mkl_include.tar/marker");
+        fs::write(&marker, "")?;
+        Library::from_archives(cfg, &archives, &digests, &dest)?;
+        assert!(marker.exists());
+
+        // Archive changed from the stamp is unpacked again
+        pack(mkl.root(), "mkl_devel-2023.1.0.data", &include)?;
+        unpack_archive(&include, &dest.join("mkl_include.tar"), None)?;
+        assert!(!marker.exists());
+
+        let err =
+            unpack_archive(&tgz, &mkl.root().join("mismatch"), Some(&digests[0])).unwrap_err();
+        assert!(err.to_string().starts_with("SHA-256 mismatch"), "{}", err);
+        assert!(!mkl.root().join("mismatch").exists());
+
+        let err = Library::from_archives(cfg, &archives, &digests[..1], &dest).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 SHA-256 digests are given for 2 archives"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sha256_digest() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("intel-mkl-tool-sha256-{}", std::process::id()));
+        fs::write(&path, b"abc")?;
+        let digest = sha256_file(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use intel_mkl_tool::*;
+use std::path::PathBuf;
+
+/// Seek Intel(R) MKL installed in system, and package it
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+enum Opt {
+    /// Seek MKL libraries for all configs
+    Seek,
+
+    /// Package MKL libraries for a config into oci-archive
+    Package {
+        /// MKL config, e.g. `mkl-static-lp64-seq`
+        #[clap(long, short)]
+        config: Config,
+
+        /// Directory to seek MKL instead of default paths
+        #[clap(long)]
+        root: Option<PathBuf>,
+
+        /// Registry where the image will be pushed, e.g. `localhost:5000`
+        #[clap(long, default_value = "ghcr.io")]
+        registry: String,
+
+        /// Tag of the image. Defaults to `{MKL version}-local`
+        #[clap(long)]
+        tag: Option<String>,
+
+        /// Full image name, overrides `--registry` and `--tag`
+        #[clap(long)]
+        name: Option<String>,
+
+        /// Output path of oci-archive. Defaults to `{config}.tar`
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+
+        /// Push the archive into the registry
+        #[clap(long)]
+        push: bool,
+    },
+
+    /// Write version, config and file digests of MKL into a lockfile
+    Lock {
+        /// MKL config, e.g. `mkl-static-lp64-seq`
+        #[clap(long, short)]
+        config: Config,
+
+        /// Directory to seek MKL instead of default paths
+        #[clap(long)]
+        root: Option<PathBuf>,
+
+        /// Output path of the lockfile
+        #[clap(long, short, default_value = LOCK_FILE_NAME)]
+        output: PathBuf,
+    },
+
+    /// Generate pkg-config files `{config}.pc` for all configs found
+    PkgConfig {
+        /// Directory to seek MKL instead of default paths
+        #[clap(long)]
+        root: Option<PathBuf>,
+
+        /// Directory where `.pc` files are written
+        #[clap(long, short, default_value = "pkgconfig")]
+        output: PathBuf,
+    },
+
+    /// Generate CMake package config `MKLConfig.cmake` with imported targets, e.g. `MKL::mkl_static_lp64_seq`
+    Cmake {
+        /// MKL configs exported as targets. Defaults to all configs found
+        #[clap(long, short)]
+        config: Vec<Config>,
+
+        /// Directory to seek MKL instead of default paths
+        #[clap(long)]
+        root: Option<PathBuf>,
+
+        /// Directory where the config is written
+        #[clap(long, short, default_value = "cmake")]
+        output: PathBuf,
+    },
+
+    /// Check MKL and OpenMP runtime required by a dynamically linked binary are resolved on this machine
+    Doctor {
+        /// Path of ELF executable or shared library
+        binary: PathBuf,
+    },
+}
+
+fn find(config: Config, root: Option<PathBuf>) -> Result<Library> {
+    match root {
+        Some(root) => match Library::seek_directory(config, &root)? {
+            Some(lib) => Ok(lib),
+            None => bail!("{} is not found in {}", config, root.display()),
+        },
+        None => Library::new(config),
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+    match Opt::parse() {
+        Opt::Seek => {
+            for cfg in Config::possibles() {
+                match Library::new(cfg) {
+                    Ok(lib) => println!("{:<24} {}", cfg, lib.library_dir.display()),
+                    Err(_) => println!("{:<24} (not found)", cfg),
+                }
+            }
+        }
+        Opt::Package {
+            config,
+            root,
+            registry,
+            tag,
+            name,
+            output,
+            push,
+        } => {
+            let lib = find(config, root)?;
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let tag = match tag {
+                        Some(tag) => tag,
+                        None => lib.local_tag()?,
+                    };
+                    image_name(&registry, config, &tag)
+                }
+            };
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.tar", config)));
+            lib.package(&name, &output)?;
+            if push {
+                push_package(&output)?;
+            }
+        }
+        Opt::Lock {
+            config,
+            root,
+            output,
+        } => {
+            let lib = find(config, root)?;
+            MklLock::from_library(&lib)?.write(&output)?;
+            log::info!(
+                "Lock MKL in {} into {}",
+                lib.library_dir.display(),
+                output.display()
+            );
+        }
+        Opt::PkgConfig { root, output } => match root {
+            Some(root) => {
+                write_pkg_config_files(&root, &output)?;
+            }
+            None => {
+                std::fs::create_dir_all(&output)?;
+                for lib in Library::available() {
+                    let path = output.join(format!("{}.pc", lib.config));
+                    std::fs::write(&path, lib.pkg_config_file())?;
+                    log::info!("Write {}", path.display());
+                }
+            }
+        },
+        Opt::Cmake {
+            config,
+            root,
+            output,
+        } => {
+            let libs = if config.is_empty() {
+                Config::possibles()
+                    .into_iter()
+                    .filter_map(|cfg| find(cfg, root.clone()).ok())
+                    .collect()
+            } else {
+                config
+                    .into_iter()
+                    .map(|cfg| find(cfg, root.clone()))
+                    .collect::<Result<Vec<_>>>()?
+            };
+            write_cmake_config(&libs, &output)?;
+        }
+        Opt::Doctor { binary } => {
+            let report = check_runtime(&binary)?;
+            print!("{}", report);
+            let problems = report.problems();
+            if !problems.is_empty() {
+                for problem in &problems {
+                    log::error!("{}", problem);
+                }
+                bail!(
+                    "{} problem(s) found in {}",
+                    problems.len(),
+                    binary.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Generate CMake package config of found MKL for projects mixing Rust and C/C++
+
+use crate::{mkl_file_name, mkl_libs, Library, LinkType};
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File name of CMake package config written by [write_cmake_config], found by `find_package(MKL CONFIG)`
+pub const CMAKE_CONFIG_FILE_NAME: &str = "MKLConfig.cmake";
+
+/// File name of CMake package version file written by [write_cmake_config]
+pub const CMAKE_CONFIG_VERSION_FILE_NAME: &str = "MKLConfigVersion.cmake";
+
+impl Library {
+    /// Name of CMake imported target, e.g. `MKL::mkl_static_lp64_seq`
+    pub fn cmake_target_name(&self) -> String {
+        format!("MKL::{}", self.config.to_string().replace('-', "_"))
+    }
+
+    /// CMake commands defining an `INTERFACE IMPORTED` target named [Self::cmake_target_name]
+    ///
+    /// - `INTERFACE_INCLUDE_DIRECTORIES` is [Library::include_dir]
+    /// - `INTERFACE_COMPILE_DEFINITIONS` is [Library::defines], e.g. `MKL_ILP64`
+    /// - `INTERFACE_LINK_LIBRARIES` lists absolute paths of MKL libraries and OpenMP runtime
+    ///   in the same order as [Library::print_cargo_metadata]
+    pub fn cmake_target(&self) -> String {
+        let target = self.cmake_target_name();
+        let mut libs: Vec<String> = mkl_libs(self.config)
+            .iter()
+            .map(|name| cmake_path(&self.library_dir.join(mkl_file_name(self.config.link, name))))
+            .collect();
+        if !cfg!(any(target_os = "windows", target_os = "macos")) {
+            match self.config.link {
+                LinkType::Static => {
+                    libs.insert(0, "-Wl,--start-group".to_string());
+                    libs.push("-Wl,--end-group".to_string());
+                }
+                LinkType::Dynamic => libs.insert(0, "-Wl,--no-as-needed".to_string()),
+            }
+        }
+        if let Some((link, dir)) = self.openmp_runtime_dir() {
+            libs.push(cmake_path(&dir.join(self.openmp_runtime.file_name(link))));
+        }
+        if !cfg!(target_os = "windows") {
+            libs.extend(["pthread", "m", "dl"].iter().map(|lib| lib.to_string()));
+        }
+        let defines: Vec<String> = self
+            .defines()
+            .into_iter()
+            .map(|(key, value)| match value {
+                Some(value) => format!("{}={}", key, value),
+                None => key.to_string(),
+            })
+            .collect();
+
+        format!(
+            "if(NOT TARGET {target})\n  \
+               add_library({target} INTERFACE IMPORTED)\n  \
+               set_target_properties({target} PROPERTIES\n    \
+                 INTERFACE_INCLUDE_DIRECTORIES \"{include}\"\n    \
+                 INTERFACE_COMPILE_DEFINITIONS \"{defines}\"\n    \
+                 INTERFACE_LINK_LIBRARIES \"{libs}\"\n  \
+               )\n\
+             endif()\n",
+            target = target,
+            include = cmake_path(&self.include_dir),
+            defines = defines.join(";"),
+            libs = libs.join(";"),
+        )
+    }
+}
+
+/// Write CMake package config `MKLConfig.cmake` and `MKLConfigVersion.cmake` into `dest` directory
+///
+/// All `libs` become imported targets, see [Library::cmake_target].
+/// They must be the same MKL version, e.g. found in the same installation.
+/// C/C++ side finds them by `find_package(MKL CONFIG REQUIRED PATHS {dest} NO_DEFAULT_PATH)`,
+/// and links e.g. `MKL::mkl_static_lp64_seq`.
+///
+/// Returns the path of `MKLConfig.cmake`.
+pub fn write_cmake_config(libs: &[Library], dest: &Path) -> Result<PathBuf> {
+    let first = match libs.first() {
+        Some(lib) => lib,
+        None => bail!("No MKL to write CMake config"),
+    };
+    let version = first.version().ok();
+    if libs.iter().any(|lib| lib.version().ok() != version) {
+        bail!("Different versions of MKL cannot be written into a CMake config");
+    }
+    let version = version.map(|(year, minor, update)| format!("{}.{}.{}", year, minor, update));
+
+    let mut config = String::from("# Generated by intel-mkl-tool\n\n");
+    if let Some(version) = &version {
+        config += &format!("set(MKL_VERSION \"{}\")\n", version);
+    }
+    let names: Vec<String> = libs.iter().map(|lib| lib.cmake_target_name()).collect();
+    config += &format!("set(MKL_IMPORTED_TARGETS \"{}\")\n", names.join(";"));
+    config += "set(MKL_FOUND TRUE)\n";
+    for lib in libs {
+        config += "\n";
+        config += &lib.cmake_target();
+    }
+
+    fs::create_dir_all(dest)?;
+    let path = dest.join(CMAKE_CONFIG_FILE_NAME);
+    fs::write(&path, config).with_context(|| format!("Failed to write {}", path.display()))?;
+    if let Some(version) = version {
+        let version_file = format!(
+            "# Generated by intel-mkl-tool\n\
+             set(PACKAGE_VERSION \"{version}\")\n\
+             if(PACKAGE_FIND_VERSION VERSION_GREATER PACKAGE_VERSION)\n  \
+               set(PACKAGE_VERSION_COMPATIBLE FALSE)\n\
+             else()\n  \
+               set(PACKAGE_VERSION_COMPATIBLE TRUE)\n  \
+               if(PACKAGE_FIND_VERSION STREQUAL PACKAGE_VERSION)\n    \
+                 set(PACKAGE_VERSION_EXACT TRUE)\n  \
+               endif()\n\
+             endif()\n",
+            version = version
+        );
+        fs::write(dest.join(CMAKE_CONFIG_VERSION_FILE_NAME), version_file)?;
+    }
+    log::info!("Write {}", path.display());
+    Ok(path)
+}
+
+/// Path in CMake string, which uses `/` as separator even on Windows
+fn cmake_path(path: &Path) -> String {
+    path.display().to_string().replace('\\', "/")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn cmake_config() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let libs: Vec<Library> = ["mkl-static-ilp64-iomp", "mkl-dynamic-lp64-seq"]
+            .iter()
+            .map(|name| mkl.seek(name))
+            .collect::<Result<_>>()?;
+        assert_eq!(libs[0].cmake_target_name(), "MKL::mkl_static_ilp64_iomp");
+
+        let dest = mkl.root().join("cmake");
+        let path = write_cmake_config(&libs, &dest)?;
+        let config = fs::read_to_string(path)?;
+        let lib_dir = mkl.library_dir().display().to_string();
+        assert!(config.contains("set(MKL_VERSION \"2020.0.1\")\n"));
+        assert!(config.contains(
+            "set(MKL_IMPORTED_TARGETS \"MKL::mkl_static_ilp64_iomp;MKL::mkl_dynamic_lp64_seq\")\n"
+        ));
+        assert!(config.contains("add_library(MKL::mkl_static_ilp64_iomp INTERFACE IMPORTED)\n"));
+        assert!(config.contains("INTERFACE_COMPILE_DEFINITIONS \"MKL_ILP64\"\n"));
+        assert!(config.contains(&format!(
+            "INTERFACE_LINK_LIBRARIES \"-Wl,--start-group;{lib}/libmkl_intel_ilp64.a;{lib}/libmkl_intel_thread.a;\
+             {lib}/libmkl_core.a;-Wl,--end-group;{omp}/libiomp5.a;pthread;m;dl\"\n",
+            lib = lib_dir,
+            omp = mkl.openmp_dir().display()
+        )));
+        assert!(config.contains("INTERFACE_COMPILE_DEFINITIONS \"\"\n"));
+        assert!(dest.join(CMAKE_CONFIG_VERSION_FILE_NAME).is_file());
+
+        // Different versions are not mixed
+        let other = FakeMkl::new(Layout::OneApi, (2023, 0, 0))?;
+        let other = Library::seek_directory(libs[0].config, other.root())?.unwrap();
+        assert!(write_cmake_config(&[libs[0].clone(), other], &dest).is_err());
+        Ok(())
+    }
+}
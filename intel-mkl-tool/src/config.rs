@@ -176,6 +176,186 @@ impl Config {
             .map(|name| Self::from_str(name).unwrap())
             .collect()
     }
+
+    /// This config followed by alternatives allowed by `fallbacks`
+    ///
+    /// Each [Fallback] is applied in the given order to all configs listed before it.
+    /// [DataModel] is never changed since it changes the ABI of MKL functions.
+    ///
+    /// ```
+    /// use intel_mkl_tool::*;
+    /// use std::str::FromStr;
+    ///
+    /// let cfg = Config::from_str("mkl-static-ilp64-iomp").unwrap();
+    /// let fallbacks = Fallback::parse_list("static-to-dynamic,iomp-to-seq").unwrap();
+    /// let names: Vec<String> = cfg.fallbacks(&fallbacks).iter().map(|c| c.to_string()).collect();
+    /// assert_eq!(
+    ///     names,
+    ///     [
+    ///         "mkl-static-ilp64-iomp",
+    ///         "mkl-dynamic-ilp64-iomp",
+    ///         "mkl-static-ilp64-seq",
+    ///         "mkl-dynamic-ilp64-seq",
+    ///     ]
+    /// );
+    /// ```
+    pub fn fallbacks(&self, fallbacks: &[Fallback]) -> Vec<Self> {
+        let mut configs = vec![*self];
+        for fallback in fallbacks {
+            for i in 0..configs.len() {
+                if let Some(cfg) = fallback.apply(configs[i]) {
+                    if !configs.contains(&cfg) {
+                        configs.push(cfg);
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+/// Environment variable to set [OpenMpLinkPolicy], e.g. `INTEL_MKL_OPENMP_LINK=dynamic`
+pub const OPENMP_LINK_POLICY_ENV: &str = "INTEL_MKL_OPENMP_LINK";
+
+/// Which OpenMP runtime, static (`libiomp5.a`) or dynamic (`libiomp5.so`), is linked for `mkl-*-*-iomp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMpLinkPolicy {
+    /// Only static runtime
+    StrictStatic,
+    /// Only dynamic runtime
+    StrictDynamic,
+    /// The same link type as [Config::link], and the other one if it is not found (default)
+    ///
+    /// Some distributions do not provide `libiomp5.a`.
+    PreferConfig,
+    /// Either of them regardless of [Config::link]. Dynamic runtime is preferred
+    /// since Intel does not recommend linking OpenMP runtime statically.
+    Any,
+}
+
+impl Default for OpenMpLinkPolicy {
+    fn default() -> Self {
+        OpenMpLinkPolicy::PreferConfig
+    }
+}
+
+impl fmt::Display for OpenMpLinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenMpLinkPolicy::StrictStatic => write!(f, "static"),
+            OpenMpLinkPolicy::StrictDynamic => write!(f, "dynamic"),
+            OpenMpLinkPolicy::PreferConfig => write!(f, "prefer-config"),
+            OpenMpLinkPolicy::Any => write!(f, "any"),
+        }
+    }
+}
+
+impl FromStr for OpenMpLinkPolicy {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "static" => OpenMpLinkPolicy::StrictStatic,
+            "dynamic" => OpenMpLinkPolicy::StrictDynamic,
+            "prefer-config" => OpenMpLinkPolicy::PreferConfig,
+            "any" => OpenMpLinkPolicy::Any,
+            another => bail!(
+                "Invalid OpenMP link policy: {} (expected static, dynamic, prefer-config, or any)",
+                another
+            ),
+        })
+    }
+}
+
+impl OpenMpLinkPolicy {
+    /// Read [OPENMP_LINK_POLICY_ENV], or default if it is not set
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(OPENMP_LINK_POLICY_ENV) {
+            Ok(policy) if !policy.is_empty() => Self::from_str(&policy),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Acceptable link types of OpenMP runtime for `config` in the order of preference
+    pub fn link_types(&self, config: Config) -> Vec<LinkType> {
+        match self {
+            OpenMpLinkPolicy::StrictStatic => vec![LinkType::Static],
+            OpenMpLinkPolicy::StrictDynamic => vec![LinkType::Dynamic],
+            OpenMpLinkPolicy::PreferConfig => vec![config.link, config.link.otherwise()],
+            OpenMpLinkPolicy::Any => vec![LinkType::Dynamic, LinkType::Static],
+        }
+    }
+}
+
+/// Change of [Config] acceptable when the requested one is not found, see [Config::fallbacks]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// `mkl-static-*-*` to `mkl-dynamic-*-*`
+    StaticToDynamic,
+    /// `mkl-dynamic-*-*` to `mkl-static-*-*`
+    DynamicToStatic,
+    /// `mkl-*-*-iomp` to `mkl-*-*-seq`
+    OpenMPToSequential,
+    /// `mkl-*-*-seq` to `mkl-*-*-iomp`
+    SequentialToOpenMP,
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fallback::StaticToDynamic => write!(f, "static-to-dynamic"),
+            Fallback::DynamicToStatic => write!(f, "dynamic-to-static"),
+            Fallback::OpenMPToSequential => write!(f, "iomp-to-seq"),
+            Fallback::SequentialToOpenMP => write!(f, "seq-to-iomp"),
+        }
+    }
+}
+
+impl FromStr for Fallback {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "static-to-dynamic" => Fallback::StaticToDynamic,
+            "dynamic-to-static" => Fallback::DynamicToStatic,
+            "iomp-to-seq" => Fallback::OpenMPToSequential,
+            "seq-to-iomp" => Fallback::SequentialToOpenMP,
+            another => bail!("Invalid fallback: {}", another),
+        })
+    }
+}
+
+impl Fallback {
+    /// Parse comma separated list, e.g. `static-to-dynamic,iomp-to-seq`
+    pub fn parse_list(input: &str) -> Result<Vec<Self>> {
+        input
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+
+    /// Alternative of `cfg`, or `None` if this fallback is not applicable
+    pub fn apply(&self, cfg: Config) -> Option<Config> {
+        match (self, cfg.link, cfg.parallel) {
+            (Fallback::StaticToDynamic, LinkType::Static, _) => Some(Config {
+                link: LinkType::Dynamic,
+                ..cfg
+            }),
+            (Fallback::DynamicToStatic, LinkType::Dynamic, _) => Some(Config {
+                link: LinkType::Static,
+                ..cfg
+            }),
+            (Fallback::OpenMPToSequential, _, Threading::OpenMP) => Some(Config {
+                parallel: Threading::Sequential,
+                ..cfg
+            }),
+            (Fallback::SequentialToOpenMP, _, Threading::Sequential) => Some(Config {
+                parallel: Threading::OpenMP,
+                ..cfg
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -215,4 +395,42 @@ mod tests {
         assert!(Config::from_str("mkl-static-lp64-omp").is_err());
         Ok(())
     }
+
+    #[test]
+    fn fallbacks_keep_data_model() -> Result<()> {
+        let all = [
+            Fallback::StaticToDynamic,
+            Fallback::DynamicToStatic,
+            Fallback::OpenMPToSequential,
+            Fallback::SequentialToOpenMP,
+        ];
+        for cfg in Config::possibles() {
+            let fallbacks = cfg.fallbacks(&all);
+            assert_eq!(fallbacks[0], cfg);
+            assert_eq!(fallbacks.len(), 4);
+            assert!(fallbacks.iter().all(|c| c.index_size == cfg.index_size));
+        }
+
+        let cfg = Config::from_str("mkl-dynamic-lp64-seq")?;
+        assert_eq!(cfg.fallbacks(&[Fallback::StaticToDynamic]), vec![cfg]);
+        assert!(Fallback::parse_list("static-to-dynamic,lp64-to-ilp64").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn openmp_link_policy() -> Result<()> {
+        let cfg = Config::from_str("mkl-static-lp64-iomp")?;
+        for (name, types) in [
+            ("static", vec![LinkType::Static]),
+            ("dynamic", vec![LinkType::Dynamic]),
+            ("prefer-config", vec![LinkType::Static, LinkType::Dynamic]),
+            ("any", vec![LinkType::Dynamic, LinkType::Static]),
+        ] {
+            let policy = OpenMpLinkPolicy::from_str(name)?;
+            assert_eq!(policy.to_string(), name);
+            assert_eq!(policy.link_types(cfg), types);
+        }
+        assert!(OpenMpLinkPolicy::from_str("strict").is_err());
+        Ok(())
+    }
 }
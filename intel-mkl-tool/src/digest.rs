@@ -0,0 +1,25 @@
+//! SHA-256 digest of files, enabled by `archive` or `sbom` feature
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::{fs, io::Read, path::Path};
+
+/// Hex encoded SHA-256 digest of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut f =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0_u8; 1 << 16];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
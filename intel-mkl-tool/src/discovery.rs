@@ -0,0 +1,562 @@
+//! Customizable chain of sources to seek MKL
+
+use crate::{
+    check_elf_target, mkl_file_name, mkl_libs, Config, Library, LinkType, OpenMpLinkPolicy,
+    OpenMpRuntime, SeekOptions, Threading,
+};
+use anyhow::{bail, Result};
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+/// Where to seek MKL, used by [Discovery]
+pub trait Source {
+    /// Name of source shown in logs
+    fn name(&self) -> String;
+
+    /// Seek MKL for `config`. Returns empty if not found.
+    fn seek(&self, config: Config) -> Result<Vec<Library>>;
+}
+
+impl fmt::Debug for dyn Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Ask to `pkg-config`, see [Library::pkg_config]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PkgConfig;
+
+impl Source for PkgConfig {
+    fn name(&self) -> String {
+        "pkg-config".into()
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        Ok(Library::pkg_config(config)?.into_iter().collect())
+    }
+}
+
+/// Seek the directory specified by an environment variable, e.g. `MKLROOT`
+#[derive(Debug, Clone)]
+pub struct EnvVar(pub String);
+
+impl EnvVar {
+    pub fn new(key: &str) -> Self {
+        EnvVar(key.to_string())
+    }
+}
+
+impl Source for EnvVar {
+    fn name(&self) -> String {
+        format!("${}", self.0)
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        match env::var_os(&self.0) {
+            Some(dir) => {
+                log::info!("{} environment variable is detected: {:?}", self.0, dir);
+                Ok(Library::seek_directory(config, dir)?.into_iter().collect())
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Seek explicitly given directories by [Library::seek_directory_with]
+#[derive(Debug, Clone)]
+pub struct Paths {
+    pub paths: Vec<PathBuf>,
+    pub options: SeekOptions,
+}
+
+impl Paths {
+    pub fn new<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Paths {
+            paths: paths.into_iter().map(|p| p.as_ref().to_owned()).collect(),
+            options: SeekOptions::default(),
+        }
+    }
+
+    /// Well-known installation paths of MKL
+    ///
+    /// - `/opt/intel` for Linux
+    /// - `C:/Program Files (x86)/IntelSWTools/` and `C:/Program Files (x86)/Intel/oneAPI/` for Windows
+    pub fn well_known() -> Self {
+        Paths::new([
+            "/opt/intel",
+            "C:/Program Files (x86)/IntelSWTools/",
+            "C:/Program Files (x86)/Intel/oneAPI/",
+        ])
+    }
+
+    pub fn options(mut self, options: SeekOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+impl Source for Paths {
+    fn name(&self) -> String {
+        let paths: Vec<_> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        format!("paths [{}]", paths.join(", "))
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        let mut libs = Vec::new();
+        for path in &self.paths {
+            libs.extend(Library::seek_directory_with(config, path, &self.options)?);
+        }
+        Ok(libs)
+    }
+}
+
+/// Conda environment specified by `$CONDA_PREFIX`
+///
+/// `mkl-include` and `mkl-devel` (or `mkl-static`) packages place
+/// headers in `include/` and libraries in `lib/`
+/// (`Library/include/` and `Library/lib/` on Windows).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Conda;
+
+impl Source for Conda {
+    fn name(&self) -> String {
+        "conda ($CONDA_PREFIX)".into()
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        let prefix = match env::var_os("CONDA_PREFIX") {
+            Some(prefix) => PathBuf::from(prefix),
+            None => return Ok(Vec::new()),
+        };
+        let prefix = if cfg!(target_os = "windows") {
+            prefix.join("Library")
+        } else {
+            prefix
+        };
+        Ok(
+            Library::from_dirs(config, prefix.join("include"), prefix.join("lib"), &[])?
+                .into_iter()
+                .collect(),
+        )
+    }
+}
+
+/// Debian/Ubuntu multiarch layout of `intel-mkl` package
+///
+/// Headers are in `/usr/include/mkl`, and libraries are in `/usr/lib/{triple}`, e.g. `/usr/lib/x86_64-linux-gnu`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Multiarch;
+
+impl Source for Multiarch {
+    fn name(&self) -> String {
+        "multiarch (/usr/lib/{triple})".into()
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_else(|_| env::consts::ARCH.into());
+        let endian = env::var("CARGO_CFG_TARGET_ENDIAN").unwrap_or_else(|_| {
+            if cfg!(target_endian = "big") {
+                "big".into()
+            } else {
+                "little".into()
+            }
+        });
+        let triple = match multiarch_triple(&arch, &endian) {
+            Some(triple) => triple,
+            None => return Ok(Vec::new()),
+        };
+        let lib_dir = Path::new("/usr/lib").join(triple);
+        Ok(
+            Library::from_dirs(config, "/usr/include/mkl", lib_dir, &[])?
+                .into_iter()
+                .collect(),
+        )
+    }
+}
+
+/// Debian multiarch tuple for `CARGO_CFG_TARGET_ARCH` and `CARGO_CFG_TARGET_ENDIAN`
+///
+/// See <https://wiki.debian.org/Multiarch/Tuples>. Returns `None` for architectures Debian does not have.
+fn multiarch_triple(arch: &str, endian: &str) -> Option<&'static str> {
+    Some(match (arch, endian) {
+        ("x86_64", _) => "x86_64-linux-gnu",
+        ("x86", _) => "i386-linux-gnu",
+        ("aarch64", _) => "aarch64-linux-gnu",
+        ("arm", _) => "arm-linux-gnueabihf",
+        ("powerpc64", "little") => "powerpc64le-linux-gnu",
+        ("powerpc64", _) => "powerpc64-linux-gnu",
+        ("riscv64", _) => "riscv64-linux-gnu",
+        ("s390x", _) => "s390x-linux-gnu",
+        _ => return None,
+    })
+}
+
+/// Intel oneAPI installation specified by `$ONEAPI_ROOT`, or its default path
+/// `/opt/intel/oneapi` or `C:/Program Files (x86)/Intel/oneAPI/`
+///
+/// OpenMP runtime is seeked in the compiler directory of oneAPI.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OneApi;
+
+impl Source for OneApi {
+    fn name(&self) -> String {
+        "oneAPI".into()
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        let root = match env::var_os("ONEAPI_ROOT") {
+            Some(root) => PathBuf::from(root),
+            None if cfg!(target_os = "windows") => {
+                PathBuf::from("C:/Program Files (x86)/Intel/oneAPI/")
+            }
+            None => PathBuf::from("/opt/intel/oneapi"),
+        };
+        Ok(Library::seek_directory(config, root)?.into_iter().collect())
+    }
+}
+
+/// Custom source defined by a closure, see [Discovery::custom]
+pub struct FnSource<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> Source for FnSource<F>
+where
+    F: Fn(Config) -> Result<Option<Library>>,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn seek(&self, config: Config) -> Result<Vec<Library>> {
+        Ok((self.f)(config)?.into_iter().collect())
+    }
+}
+
+/// How [Discovery::run] treats multiple sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Stop at the first source which finds MKL
+    FirstMatch,
+    /// Try all sources and collect every MKL found
+    CollectAll,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::FirstMatch
+    }
+}
+
+/// Ordered chain of [Source]s to seek MKL
+///
+/// ```no_run
+/// use intel_mkl_tool::*;
+/// use std::str::FromStr;
+///
+/// let cfg = Config::from_str("mkl-static-lp64-seq").unwrap();
+/// let lib = Discovery::new(cfg)
+///     .source(EnvVar::new("MY_MKL_DIR"))
+///     .source(Conda)
+///     .custom("vendored", |cfg| Library::seek_directory(cfg, "/path/to/vendor/mkl"))
+///     .source(PkgConfig)
+///     .find()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Discovery {
+    config: Config,
+    sources: Vec<Box<dyn Source>>,
+    mode: Mode,
+}
+
+impl Discovery {
+    /// Discovery without any sources
+    pub fn new(config: Config) -> Self {
+        Discovery {
+            config,
+            sources: Vec::new(),
+            mode: Mode::default(),
+        }
+    }
+
+    /// Discovery used in [Library::new], i.e. [PkgConfig], `$MKLROOT`, and [Paths::well_known]
+    pub fn with_defaults(config: Config) -> Self {
+        Self::new(config)
+            .source(PkgConfig)
+            .source(EnvVar::new("MKLROOT"))
+            .source(Paths::well_known())
+    }
+
+    /// Append a source to be tried after the sources already added
+    pub fn source(mut self, source: impl Source + 'static) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Append a source defined by a closure
+    pub fn custom<F>(self, name: &str, f: F) -> Self
+    where
+        F: Fn(Config) -> Result<Option<Library>> + 'static,
+    {
+        self.source(FnSource {
+            name: name.to_string(),
+            f,
+        })
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Try sources in order, see [Mode]
+    ///
+    /// Errors in each source are logged and the next source is tried.
+    /// Libraries found by several sources are listed only once.
+    pub fn run(&self) -> Vec<Library> {
+        self.run_with(self.mode)
+    }
+
+    /// First library found. This stops at the first source which finds MKL regardless of [Mode].
+    pub fn find(&self) -> Result<Library> {
+        match self.run_with(Mode::FirstMatch).into_iter().next() {
+            Some(lib) => Ok(lib),
+            None => bail!("Intel MKL not found in system"),
+        }
+    }
+
+    fn run_with(&self, mode: Mode) -> Vec<Library> {
+        let mut found: Vec<Library> = Vec::new();
+        for source in &self.sources {
+            log::info!("Seek {} in {}", self.config, source.name());
+            match source.seek(self.config) {
+                Ok(libs) => {
+                    if libs.is_empty() {
+                        log::info!("{} is not found in {}", self.config, source.name());
+                    }
+                    for lib in libs {
+                        if !found.contains(&lib) {
+                            found.push(lib);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Failed to seek {}: {:#}", source.name(), e),
+            }
+            if mode == Mode::FirstMatch && !found.is_empty() {
+                break;
+            }
+        }
+        found
+    }
+}
+
+impl Library {
+    /// Create from directories given explicitly
+    ///
+    /// OpenMP runtime for `mkl-*-*-iomp` is seeked in `library_dir` and `openmp_dirs`.
+    /// Libraries whose ELF class or machine does not match to the build target are not used, see [check_elf_target].
+    /// Returns `Ok(None)` if the directories do not contain MKL, or it fails [Library::check_consistency].
+    pub fn from_dirs(
+        config: Config,
+        include_dir: impl AsRef<Path>,
+        library_dir: impl AsRef<Path>,
+        openmp_dirs: &[PathBuf],
+    ) -> Result<Option<Self>> {
+        Self::from_dirs_with(
+            config,
+            include_dir,
+            library_dir,
+            openmp_dirs,
+            &OpenMpRuntime::candidates_from_env(),
+        )
+    }
+
+    /// [Library::from_dirs] accepting OpenMP runtimes in `runtimes` in the order of preference,
+    /// instead of reading [crate::ALLOW_LIBOMP_ENV].
+    /// An empty `runtimes` falls back to [OpenMpRuntime::Intel].
+    pub fn from_dirs_with(
+        config: Config,
+        include_dir: impl AsRef<Path>,
+        library_dir: impl AsRef<Path>,
+        openmp_dirs: &[PathBuf],
+        runtimes: &[OpenMpRuntime],
+    ) -> Result<Option<Self>> {
+        let include_dir = include_dir.as_ref();
+        let library_dir = library_dir.as_ref();
+        if !include_dir.is_dir() || !library_dir.is_dir() {
+            return Ok(None);
+        }
+        for name in mkl_libs(config) {
+            let path = library_dir.join(mkl_file_name(config.link, &name));
+            if !path.is_file() {
+                continue;
+            }
+            if let Err(e) = check_elf_target(&path) {
+                log::warn!("Skip {}: {}", library_dir.display(), e);
+                return Ok(None);
+            }
+        }
+        let mut lib = Library {
+            config,
+            include_dir: include_dir.to_owned(),
+            library_dir: library_dir.to_owned(),
+            iomp5_static_dir: None,
+            iomp5_dynamic_dir: None,
+            openmp_link_policy: OpenMpLinkPolicy::from_env()?,
+            openmp_runtime: OpenMpRuntime::default(),
+        };
+        if config.parallel == Threading::OpenMP {
+            let runtimes = if runtimes.is_empty() {
+                &[OpenMpRuntime::Intel][..]
+            } else {
+                runtimes
+            };
+            // `libomp` is used only if `libiomp5` is not found in any directories
+            for &runtime in runtimes {
+                lib.openmp_runtime = runtime;
+                for dir in
+                    std::iter::once(library_dir).chain(openmp_dirs.iter().map(|d| d.as_path()))
+                {
+                    for link in [LinkType::Static, LinkType::Dynamic] {
+                        let path = dir.join(runtime.file_name(link));
+                        if !path.is_file() {
+                            continue;
+                        }
+                        if let Err(e) = check_elf_target(&path) {
+                            log::warn!("Skip {}: {}", path.display(), e);
+                            continue;
+                        }
+                        let found = match link {
+                            LinkType::Static => &mut lib.iomp5_static_dir,
+                            LinkType::Dynamic => &mut lib.iomp5_dynamic_dir,
+                        };
+                        if found.is_none() {
+                            *found = Some(dir.to_owned());
+                        }
+                    }
+                }
+                if lib.openmp_link_type().is_some() {
+                    break;
+                }
+                lib.iomp5_static_dir = None;
+                lib.iomp5_dynamic_dir = None;
+            }
+        }
+        match lib.check_consistency() {
+            Ok(warnings) => {
+                for warning in warnings {
+                    log::warn!("{}", warning);
+                }
+                Ok(Some(lib))
+            }
+            Err(e) => {
+                log::info!(
+                    "{} is not usable in {}: {}",
+                    config,
+                    library_dir.display(),
+                    e
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_os = "linux")]
+    use crate::{testing::*, ElfIdent};
+    #[cfg(target_os = "linux")]
+    use std::{fs, str::FromStr};
+
+    #[test]
+    fn multiarch() {
+        assert_eq!(
+            multiarch_triple("x86_64", "little"),
+            Some("x86_64-linux-gnu")
+        );
+        assert_eq!(multiarch_triple("x86", "little"), Some("i386-linux-gnu"));
+        assert_eq!(
+            multiarch_triple("arm", "little"),
+            Some("arm-linux-gnueabihf")
+        );
+        assert_eq!(
+            multiarch_triple("powerpc64", "little"),
+            Some("powerpc64le-linux-gnu")
+        );
+        assert_eq!(multiarch_triple("wasm32", "little"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_dirs() -> Result<()> {
+        for layout in [Layout::Conda, Layout::Multiarch] {
+            let mkl = FakeMkl::new(layout, (2023, 1, 0))?;
+            let cfg = Config::from_str("mkl-dynamic-ilp64-iomp")?;
+            let lib = Library::from_dirs(cfg, mkl.include_dir(), mkl.library_dir(), &[])?.unwrap();
+            assert_eq!(lib.iomp5_dynamic_dir, Some(mkl.library_dir()));
+        }
+
+        // Libraries for i386 are rejected on x86_64
+        if ElfIdent::target().map(|target| target.machine) != Some(62) {
+            return Ok(());
+        }
+        let mut i386 = b"\x7fELF\x01\x01\x01".to_vec();
+        i386.resize(18, 0);
+        i386.extend_from_slice(&3_u16.to_le_bytes());
+        let mkl = FakeMkl::new(Layout::Conda, (2023, 1, 0))?;
+        let cfg = Config::from_str("mkl-dynamic-lp64-iomp")?;
+        let openmp_dir = mkl.root().join("openmp");
+        fs::create_dir_all(&openmp_dir)?;
+        fs::write(openmp_dir.join("libiomp5.so"), "")?;
+        fs::write(mkl.library_dir().join("libiomp5.so"), &i386)?;
+        let lib = Library::from_dirs(
+            cfg,
+            mkl.include_dir(),
+            mkl.library_dir(),
+            std::slice::from_ref(&openmp_dir),
+        )?
+        .unwrap();
+        assert_eq!(lib.iomp5_dynamic_dir, Some(openmp_dir.clone()));
+        fs::write(mkl.library_dir().join("libmkl_core.so"), &i386)?;
+        assert!(
+            Library::from_dirs(cfg, mkl.include_dir(), mkl.library_dir(), &[openmp_dir])?.is_none()
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn discovery() -> Result<()> {
+        let oneapi = FakeMkl::new(Layout::OneApi, (2023, 0, 0))?;
+        let classic = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let cfg = Config::from_str("mkl-static-lp64-seq")?;
+
+        // Sources are tried in the order of addition
+        let discovery = Discovery::new(cfg)
+            .source(Paths::new([oneapi.root()]))
+            .source(Paths::new([classic.root()]));
+        let found = discovery.find()?;
+        assert_eq!(found.library_dir, oneapi.library_dir());
+
+        let all = Discovery::new(cfg)
+            .source(Paths::new([oneapi.root()]))
+            .source(Paths::new([classic.root()]))
+            .mode(Mode::CollectAll)
+            .run();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].library_dir, classic.library_dir());
+
+        // Unset environment variable is an empty source
+        let key = format!("INTEL_MKL_TOOL_TEST_UNSET_{}", std::process::id());
+        let none = Discovery::new(cfg).source(EnvVar::new(&key)).find();
+        assert!(none.is_err());
+        Ok(())
+    }
+}
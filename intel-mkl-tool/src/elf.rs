@@ -0,0 +1,505 @@
+//! Minimal reader of ELF headers to reject libraries built for another target.
+//!
+//! Only the identification part of the ELF header, i.e. class (32/64-bit),
+//! byte order and machine, is read.
+//! Static libraries (`*.a`) are `ar` archives of ELF objects,
+//! and the header of each member is checked without reading its contents.
+//!
+//! [read_elf_dynamic] additionally reads the dynamic section of executables and shared libraries
+//! to simulate the runtime loader, see [crate::check_runtime].
+
+use anyhow::{bail, Context, Result};
+use std::{
+    convert::TryFrom,
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+
+/// `EI_CLASS` of ELF header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+impl fmt::Display for ElfClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfClass::Elf32 => write!(f, "ELF32"),
+            ElfClass::Elf64 => write!(f, "ELF64"),
+        }
+    }
+}
+
+/// Class and machine (`e_machine`) of an ELF object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfIdent {
+    pub class: ElfClass,
+    pub machine: u16,
+}
+
+impl fmt::Display for ElfIdent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.class, machine_name(self.machine))
+    }
+}
+
+impl ElfIdent {
+    /// Target of current build
+    ///
+    /// This uses `CARGO_CFG_TARGET_ARCH` and `CARGO_CFG_TARGET_POINTER_WIDTH`
+    /// set by cargo while running `build.rs`, and falls back to the target
+    /// where this crate is compiled for.
+    /// Returns `None` for architectures this module does not know.
+    pub fn target() -> Option<Self> {
+        let arch = std::env::var("CARGO_CFG_TARGET_ARCH")
+            .unwrap_or_else(|_| std::env::consts::ARCH.to_string());
+        let width = std::env::var("CARGO_CFG_TARGET_POINTER_WIDTH")
+            .ok()
+            .and_then(|w| w.parse::<u32>().ok())
+            .unwrap_or(usize::BITS);
+        let machine = match arch.as_str() {
+            "x86" => 3,
+            "x86_64" => 62,
+            "arm" => 40,
+            "aarch64" => 183,
+            "powerpc" | "powerpc64" => {
+                if width == 64 {
+                    21
+                } else {
+                    20
+                }
+            }
+            "riscv32" | "riscv64" => 243,
+            "s390x" => 22,
+            _ => return None,
+        };
+        let class = if width == 64 {
+            ElfClass::Elf64
+        } else {
+            ElfClass::Elf32
+        };
+        Some(ElfIdent { class, machine })
+    }
+
+    /// Read the ELF identification from the head of a file
+    ///
+    /// Returns `Ok(None)` if the input is not an ELF object.
+    pub fn read(mut r: impl Read) -> Result<Option<Self>> {
+        let mut buf = [0_u8; 20];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match r.read(&mut buf[filled..])? {
+                0 => return Ok(None),
+                n => filled += n,
+            }
+        }
+        Ok(Self::parse(&buf))
+    }
+
+    fn parse(buf: &[u8; 20]) -> Option<Self> {
+        if &buf[0..4] != ELF_MAGIC {
+            return None;
+        }
+        let class = match buf[4] {
+            1 => ElfClass::Elf32,
+            2 => ElfClass::Elf64,
+            _ => return None,
+        };
+        let machine = match buf[5] {
+            1 => u16::from_le_bytes([buf[18], buf[19]]),
+            2 => u16::from_be_bytes([buf[18], buf[19]]),
+            _ => return None,
+        };
+        Some(ElfIdent { class, machine })
+    }
+}
+
+fn machine_name(machine: u16) -> String {
+    match machine {
+        3 => "x86".into(),
+        20 => "powerpc".into(),
+        21 => "powerpc64".into(),
+        22 => "s390x".into(),
+        40 => "arm".into(),
+        62 => "x86_64".into(),
+        183 => "aarch64".into(),
+        243 => "riscv".into(),
+        _ => format!("machine={}", machine),
+    }
+}
+
+/// ELF identifications of objects in a file
+///
+/// - A single entry for an ELF shared library or object
+/// - An entry for each ELF member of an `ar` archive
+/// - Empty for other formats, e.g. PE/COFF or Mach-O
+pub fn read_elf_idents(path: &Path) -> Result<Vec<ElfIdent>> {
+    let mut f = io::BufReader::new(
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    let mut magic = [0_u8; 8];
+    if f.read_exact(&mut magic).is_err() {
+        return Ok(Vec::new());
+    }
+    if &magic == AR_MAGIC {
+        return read_archive_idents(&mut f);
+    }
+    f.seek(SeekFrom::Start(0))?;
+    Ok(ElfIdent::read(f)?.into_iter().collect())
+}
+
+fn read_archive_idents(f: &mut (impl Read + Seek)) -> Result<Vec<ElfIdent>> {
+    let mut idents = Vec::new();
+    let mut offset = AR_MAGIC.len() as u64;
+    loop {
+        f.seek(SeekFrom::Start(offset))?;
+        let mut header = [0_u8; AR_HEADER_SIZE];
+        if f.read_exact(&mut header).is_err() {
+            break;
+        }
+        if &header[58..60] != b"`\n" {
+            bail!("Broken ar archive header at offset {}", offset);
+        }
+        let name = String::from_utf8_lossy(&header[0..16]);
+        let name = name.trim_end();
+        let size: u64 = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse()
+            .context("Invalid member size in ar archive")?;
+        let data = offset + AR_HEADER_SIZE as u64;
+
+        // Skip symbol tables and long name table
+        let is_index = name == "/"
+            || name == "//"
+            || name.starts_with("/SYM64/")
+            || name.starts_with("__.SYMDEF");
+        if !is_index {
+            // BSD archive stores the long member name in front of its data
+            let name_len = match name.strip_prefix("#1/") {
+                Some(len) => len.trim().parse().unwrap_or(0),
+                None => 0,
+            };
+            f.seek(SeekFrom::Start(data + name_len))?;
+            if let Some(ident) = ElfIdent::read(f.by_ref().take(size.saturating_sub(name_len)))? {
+                idents.push(ident);
+            }
+        }
+        // Members are aligned to 2 bytes
+        offset = data + size + size % 2;
+    }
+    Ok(idents)
+}
+
+/// Check that a library file is built for the current target
+///
+/// Files which are not ELF, e.g. libraries for Windows or macOS, pass this check
+/// since their architecture cannot be determined here.
+pub fn check_elf_target(path: &Path) -> Result<()> {
+    let target = match ElfIdent::target() {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    for ident in read_elf_idents(path)? {
+        if ident != target {
+            bail!(
+                "{} is built for {}, but target is {}",
+                path.display(),
+                ident,
+                target
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `d_tag` of dynamic section entries
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+/// `sh_type` of dynamic section
+const SHT_DYNAMIC: u32 = 6;
+
+/// Entries of ELF dynamic section used by the runtime loader
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElfDynamic {
+    /// `DT_NEEDED`, e.g. `libmkl_core.so.2`
+    pub needed: Vec<String>,
+    /// Directories in `DT_RPATH`, which may contain `$ORIGIN`
+    pub rpath: Vec<String>,
+    /// Directories in `DT_RUNPATH`, which may contain `$ORIGIN`
+    pub runpath: Vec<String>,
+}
+
+/// Read `DT_NEEDED`, `DT_RPATH` and `DT_RUNPATH` of an ELF executable or shared library
+///
+/// Returns `Ok(None)` if the file is not ELF, e.g. linker scripts or stub files,
+/// and an empty [ElfDynamic] for statically linked executables.
+/// The dynamic section is located by section headers, and thus fully stripped (`sstrip`) files are not supported.
+///
+/// Only the ELF header, section headers, and the dynamic and string table sections are read.
+pub fn read_elf_dynamic(path: &Path) -> Result<Option<ElfDynamic>> {
+    let mut f = io::BufReader::new(
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    parse_elf_dynamic(&mut f).with_context(|| format!("Broken ELF file {}", path.display()))
+}
+
+/// Read `size` bytes at `offset`, which must be within the file of `len` bytes
+fn read_range(f: &mut (impl Read + Seek), len: u64, offset: u64, size: u64) -> Result<Vec<u8>> {
+    let end = offset.checked_add(size).context("Offset overflow")?;
+    if end > len {
+        bail!("Unexpected end of file");
+    }
+    f.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0_u8; usize::try_from(size).context("Offset overflow")?];
+    f.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn parse_elf_dynamic(f: &mut (impl Read + Seek)) -> Result<Option<ElfDynamic>> {
+    let len = f.seek(SeekFrom::End(0))?;
+    if len < 20 {
+        return Ok(None);
+    }
+    // ELF header is 52 bytes for 32-bit, and 64 bytes for 64-bit
+    let header = read_range(f, len, 0, len.min(64))?;
+    if &header[0..4] != ELF_MAGIC {
+        return Ok(None);
+    }
+    let is64 = match header[4] {
+        1 => false,
+        2 => true,
+        _ => return Ok(None),
+    };
+    let le = match header[5] {
+        1 => true,
+        2 => false,
+        _ => return Ok(None),
+    };
+    // Unsigned integer of `size` bytes at `offset` in `buf`
+    let uint = |buf: &[u8], offset: u64, size: u64| -> Result<u64> {
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|start| buf.get(start..start.checked_add(size as usize)?))
+            .context("Unexpected end of file")?;
+        let mut value = 0_u64;
+        for i in 0..bytes.len() {
+            let b = if le {
+                bytes[bytes.len() - 1 - i]
+            } else {
+                bytes[i]
+            };
+            value = (value << 8) | b as u64;
+        }
+        Ok(value)
+    };
+    // Size of address and offset
+    let word = if is64 { 8 } else { 4 };
+
+    let (shoff, shentsize, shnum) = if is64 {
+        (
+            uint(&header, 0x28, 8)?,
+            uint(&header, 0x3a, 2)?,
+            uint(&header, 0x3c, 2)?,
+        )
+    } else {
+        (
+            uint(&header, 0x20, 4)?,
+            uint(&header, 0x2e, 2)?,
+            uint(&header, 0x30, 2)?,
+        )
+    };
+    let table_size = shentsize * shnum;
+    shoff
+        .checked_add(table_size)
+        .context("Section header offset overflow")?;
+    let table = read_range(f, len, shoff, table_size)?;
+    // (sh_type, sh_offset, sh_size, sh_link)
+    let section = |index: u64| -> Result<(u32, u64, u64, u64)> {
+        if index >= shnum {
+            bail!("Invalid section index {}", index);
+        }
+        let base = index * shentsize;
+        Ok((
+            uint(&table, base + 4, 4)? as u32,
+            uint(&table, base + 8 + 2 * word, word)?,
+            uint(&table, base + 8 + 3 * word, word)?,
+            uint(&table, base + 8 + 4 * word, 4)?,
+        ))
+    };
+
+    let mut dynamic = ElfDynamic::default();
+    for index in 0..shnum {
+        let (sh_type, offset, size, link) = section(index)?;
+        if sh_type != SHT_DYNAMIC {
+            continue;
+        }
+        let entries = read_range(f, len, offset, size)?;
+        let (_, strtab_offset, strtab_size, _) = section(link)?;
+        let strtab = read_range(f, len, strtab_offset, strtab_size)?;
+        let string = |offset: u64| -> Result<String> {
+            let bytes = usize::try_from(offset)
+                .ok()
+                .and_then(|start| strtab.get(start..))
+                .context("Invalid string offset")?;
+            let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            Ok(String::from_utf8_lossy(&bytes[..len]).to_string())
+        };
+        for entry in entries.chunks_exact(2 * word as usize) {
+            let tag = uint(entry, 0, word)?;
+            let value = uint(entry, word, word)?;
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => dynamic.needed.push(string(value)?),
+                DT_RPATH => dynamic
+                    .rpath
+                    .extend(string(value)?.split(':').map(|dir| dir.to_string())),
+                DT_RUNPATH => dynamic
+                    .runpath
+                    .extend(string(value)?.split(':').map(|dir| dir.to_string())),
+                _ => {}
+            }
+        }
+    }
+    Ok(Some(dynamic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf_header(class: u8, data: u8, machine: u16) -> Vec<u8> {
+        let mut buf = vec![0_u8; 64];
+        buf[0..4].copy_from_slice(ELF_MAGIC);
+        buf[4] = class;
+        buf[5] = data;
+        let m = if data == 1 {
+            machine.to_le_bytes()
+        } else {
+            machine.to_be_bytes()
+        };
+        buf[18..20].copy_from_slice(&m);
+        buf
+    }
+
+    fn ar_member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut buf = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        )
+        .into_bytes();
+        buf.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            buf.push(b'\n');
+        }
+        buf
+    }
+
+    #[test]
+    fn parse_elf_header() -> Result<()> {
+        let ident = ElfIdent::read(elf_header(2, 1, 62).as_slice())?;
+        assert_eq!(
+            ident,
+            Some(ElfIdent {
+                class: ElfClass::Elf64,
+                machine: 62
+            })
+        );
+        let ident = ElfIdent::read(elf_header(1, 2, 20).as_slice())?;
+        assert_eq!(
+            ident,
+            Some(ElfIdent {
+                class: ElfClass::Elf32,
+                machine: 20
+            })
+        );
+        assert_eq!(ElfIdent::read(&b"MZ not an ELF file..."[..])?, None);
+        assert_eq!(ElfIdent::read(&b""[..])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_archive() -> Result<()> {
+        let mut ar = AR_MAGIC.to_vec();
+        ar.extend(ar_member("/", &[0; 5]));
+        ar.extend(ar_member("a.o/", &elf_header(2, 1, 62)));
+        ar.extend(ar_member("b.o/", &elf_header(1, 1, 3)));
+        let idents = read_archive_idents(&mut io::Cursor::new(ar))?;
+        assert_eq!(
+            idents,
+            vec![
+                ElfIdent {
+                    class: ElfClass::Elf64,
+                    machine: 62
+                },
+                ElfIdent {
+                    class: ElfClass::Elf32,
+                    machine: 3
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_dynamic() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("intel-mkl-tool-elf-{}", std::process::id()));
+        let path = dir.join("libfoo.so");
+        crate::testing::write_fake_elf(
+            &path,
+            &["libmkl_core.so.2", "libc.so.6"],
+            &["$ORIGIN", "/opt/lib"],
+        )?;
+        let dynamic = read_elf_dynamic(&path)?;
+        let mut broken = fs::read(&path)?;
+        fs::remove_dir_all(&dir)?;
+
+        // Size of `.dynamic`, the third section, is not allocated when it exceeds the file
+        let shoff = u64::from_le_bytes(<[u8; 8]>::try_from(&broken[0x28..0x30])?) as usize;
+        broken[shoff + 2 * 64 + 32..shoff + 2 * 64 + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = parse_elf_dynamic(&mut io::Cursor::new(broken)).unwrap_err();
+        assert_eq!(err.to_string(), "Offset overflow");
+
+        assert_eq!(
+            dynamic,
+            Some(ElfDynamic {
+                needed: vec!["libmkl_core.so.2".into(), "libc.so.6".into()],
+                rpath: Vec::new(),
+                runpath: vec!["$ORIGIN".into(), "/opt/lib".into()],
+            })
+        );
+        assert_eq!(parse_elf_dynamic(&mut io::Cursor::new(b"!<arch>\n"))?, None);
+        assert!(parse_elf_dynamic(&mut io::Cursor::new(&elf_header(2, 1, 62)[..40])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_broken_section_header() {
+        // (e_shoff, e_shentsize, e_shnum) of 64-bit little endian header
+        let header = |shoff: u64, shentsize: u16, shnum: u16| {
+            let mut buf = elf_header(2, 1, 62);
+            buf[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+            buf[0x3a..0x3c].copy_from_slice(&shentsize.to_le_bytes());
+            buf[0x3c..0x3e].copy_from_slice(&shnum.to_le_bytes());
+            buf
+        };
+        // Section headers beyond the end of file
+        let err = parse_elf_dynamic(&mut io::Cursor::new(header(64, 64, 1))).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected end of file");
+        // Section header table overflows
+        let err = parse_elf_dynamic(&mut io::Cursor::new(header(u64::MAX - 2, 64, 1))).unwrap_err();
+        assert_eq!(err.to_string(), "Section header offset overflow");
+    }
+}
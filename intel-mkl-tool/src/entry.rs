@@ -1,10 +1,14 @@
-use crate::{Config, DataModel, LinkType, Threading};
+use crate::{
+    Config, DataModel, Discovery, LinkType, OpenMpLinkPolicy, SeekOptions, Threading,
+    OPENMP_LINK_POLICY_ENV,
+};
 use anyhow::{bail, Context, Result};
 use std::{
-    fs,
+    fmt, fs,
     io::{self, BufRead},
     path::{Path, PathBuf},
     process::Command,
+    str::FromStr,
 };
 
 /// MKL Libraries to be linked explicitly,
@@ -90,23 +94,96 @@ pub const OPENMP_RUNTIME_LIB: &str = if cfg!(target_os = "windows") {
 
 /// Filename convention for OpenMP runtime.
 pub fn openmp_runtime_file_name(link: LinkType) -> String {
-    let name = OPENMP_RUNTIME_LIB;
-    if cfg!(target_os = "windows") {
-        match link {
-            LinkType::Static => {
-                format!("{}.lib", name)
-            }
-            LinkType::Dynamic => {
-                format!("{}.dll", name)
+    OpenMpRuntime::Intel.file_name(link)
+}
+
+/// Environment variable to accept LLVM `libomp` as OpenMP runtime, e.g. `INTEL_MKL_ALLOW_LIBOMP=1`
+pub const ALLOW_LIBOMP_ENV: &str = "INTEL_MKL_ALLOW_LIBOMP";
+
+/// OpenMP runtime linked for `mkl-*-*-iomp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMpRuntime {
+    /// Intel OpenMP runtime `libiomp5` (`libiomp5md` on Windows)
+    Intel,
+    /// LLVM OpenMP runtime `libomp`, which is ABI compatible with `libiomp5`
+    Llvm,
+}
+
+impl Default for OpenMpRuntime {
+    fn default() -> Self {
+        OpenMpRuntime::Intel
+    }
+}
+
+impl fmt::Display for OpenMpRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenMpRuntime::Intel => write!(f, "intel"),
+            OpenMpRuntime::Llvm => write!(f, "llvm"),
+        }
+    }
+}
+
+impl FromStr for OpenMpRuntime {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "intel" => OpenMpRuntime::Intel,
+            "llvm" => OpenMpRuntime::Llvm,
+            another => bail!("Invalid OpenMP runtime: {}", another),
+        })
+    }
+}
+
+impl OpenMpRuntime {
+    /// Runtimes acceptable in the order of preference
+    ///
+    /// `libiomp5` is always preferred, and `libomp` is accepted only if [ALLOW_LIBOMP_ENV] is set.
+    pub fn candidates_from_env() -> Vec<Self> {
+        let allow_libomp = std::env::var(ALLOW_LIBOMP_ENV).map_or(false, |value| {
+            matches!(value.to_lowercase().as_str(), "1" | "true" | "yes")
+        });
+        if allow_libomp {
+            vec![OpenMpRuntime::Intel, OpenMpRuntime::Llvm]
+        } else {
+            vec![OpenMpRuntime::Intel]
+        }
+    }
+
+    /// Library name passed to the linker, e.g. `iomp5` for `-liomp5`
+    pub fn lib_name(&self) -> &'static str {
+        match self {
+            OpenMpRuntime::Intel => OPENMP_RUNTIME_LIB,
+            OpenMpRuntime::Llvm => {
+                if cfg!(target_os = "windows") {
+                    "libomp"
+                } else {
+                    "omp"
+                }
             }
         }
-    } else {
-        match link {
-            LinkType::Static => {
-                format!("lib{}.a", name)
+    }
+
+    /// Filename convention, e.g. `libiomp5.a` or `libomp.so`
+    pub fn file_name(&self, link: LinkType) -> String {
+        let name = self.lib_name();
+        if cfg!(target_os = "windows") {
+            match link {
+                LinkType::Static => {
+                    format!("{}.lib", name)
+                }
+                LinkType::Dynamic => {
+                    format!("{}.dll", name)
+                }
             }
-            LinkType::Dynamic => {
-                format!("lib{}.{}", name, std::env::consts::DLL_EXTENSION)
+        } else {
+            match link {
+                LinkType::Static => {
+                    format!("lib{}.a", name)
+                }
+                LinkType::Dynamic => {
+                    format!("lib{}.{}", name, std::env::consts::DLL_EXTENSION)
+                }
             }
         }
     }
@@ -130,7 +207,7 @@ pub const STATIC_EXTENSION: &str = if cfg!(any(target_os = "linux", target_os =
 ///     lib.print_cargo_metadata().unwrap();
 /// }
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Library {
     pub config: Config,
     /// Directory where `mkl.h` and `mkl_version.h` exists
@@ -140,182 +217,105 @@ pub struct Library {
 
     /// Directory where `libiomp5.a` or corresponding file exists
     ///
-    /// - They are not required for `mkl-*-*-seq` and `mkl-dynamic-*-iomp` cases, and then this is `None`.
-    /// - Both static and dynamic dir can be `Some` when `openmp-strict-link-type` feature is OFF.
+    /// - This is `None` for `mkl-*-*-seq` cases, or if static runtime is not found.
+    /// - Both static and dynamic dir can be `Some`, and [Self::openmp_link_policy] determines which is linked.
     pub iomp5_static_dir: Option<PathBuf>,
 
     /// Directory where `libiomp5.so` or corresponding file exists
     ///
-    /// - They are not required for `mkl-*-*-seq` cases and `mkl-static-*-iomp`, and then this is `None`.
-    /// - Both static and dynamic dir can be `Some` when `openmp-strict-link-type` feature is OFF.
+    /// - This is `None` for `mkl-*-*-seq` cases, or if dynamic runtime is not found.
+    /// - Both static and dynamic dir can be `Some`, and [Self::openmp_link_policy] determines which is linked.
     pub iomp5_dynamic_dir: Option<PathBuf>,
+
+    /// Which OpenMP runtime is linked, initialized by [OpenMpLinkPolicy::from_env]
+    pub openmp_link_policy: OpenMpLinkPolicy,
+
+    /// OpenMP runtime in [Self::iomp5_static_dir] and [Self::iomp5_dynamic_dir]
+    ///
+    /// This is [OpenMpRuntime::Llvm] only if `libomp` is accepted by [OpenMpRuntime::candidates_from_env]
+    /// and `libiomp5` is not found.
+    pub openmp_runtime: OpenMpRuntime,
+}
+
+/// Directory in the variable of pkg-config file, or `None` if the file or variable does not exist
+fn pkg_config_variable(config: Config, name: &str) -> Result<Option<PathBuf>> {
+    let out = Command::new("pkg-config")
+        .arg(format!("--variable={}", name))
+        .arg(config.to_string())
+        .output()
+        .context("pkg-config itself is not found")?;
+    if !out.status.success() {
+        return Ok(None);
+    }
+    let value = String::from_utf8(out.stdout)
+        .with_context(|| format!("Non-UTF8 {} of {}", name, config))?;
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        fs::canonicalize(value).unwrap_or_else(|_| PathBuf::from(value)),
+    ))
 }
 
 impl Library {
     /// Find MKL using `pkg-config`
     ///
-    /// This only use the installed prefix obtained by `pkg-config --variable=prefix`
+    /// This uses the directories obtained by `pkg-config --variable={libdir,includedir,omp_libdir}`
     ///
     /// ```text
-    /// $ pkg-config --variable=prefix mkl-static-lp64-seq
-    /// /opt/intel/mkl
+    /// $ pkg-config --variable=libdir mkl-static-lp64-seq
+    /// /opt/intel/mkl/lib/intel64
     /// ```
     ///
-    /// Then pass it to [Self::seek_directory].
-    ///
-    /// Limitation
-    /// -----------
-    /// This will not work for `mkl-*-*-iomp` configure since `libiomp5.{a,so}`
-    /// will not be found under the prefix directory of MKL.
-    /// Please use `$MKLROOT` environment variable for this case,
-    /// see [Self::new] for detail.
+    /// Then pass them to [Self::from_dirs] without seeking other directories.
+    /// `omp_libdir` is written by [Self::pkg_config_file] for `mkl-*-*-iomp` configure,
+    /// and `libiomp5.{a,so}` is seeked only in `libdir` if it is not set, e.g. in `.pc` files shipped with MKL.
     ///
     pub fn pkg_config(config: Config) -> Result<Option<Self>> {
-        if let Ok(out) = Command::new("pkg-config")
-            .arg("--variable=prefix")
-            .arg(config.to_string())
-            .output()
-        {
-            if out.status.success() {
-                let path = String::from_utf8(out.stdout).context("Non-UTF8 MKL prefix")?;
-                let prefix = Path::new(path.trim());
-                let prefix = fs::canonicalize(prefix)?;
-                log::info!("pkg-config found {} on {}", config, prefix.display());
-                Self::seek_directory(config, prefix)
-            } else {
+        let library_dir = match pkg_config_variable(config, "libdir") {
+            Ok(Some(dir)) => dir,
+            Ok(None) => {
                 log::info!("pkg-config does not find {}", config);
-                Ok(None)
+                return Ok(None);
             }
-        } else {
-            log::info!("pkg-config itself is not found");
-            Ok(None)
-        }
+            Err(e) => {
+                log::info!("{:#}", e);
+                return Ok(None);
+            }
+        };
+        let include_dir = match pkg_config_variable(config, "includedir")? {
+            Some(dir) => dir,
+            None => {
+                log::info!("pkg-config does not find includedir of {}", config);
+                return Ok(None);
+            }
+        };
+        let openmp_dirs: Vec<PathBuf> = pkg_config_variable(config, "omp_libdir")?
+            .into_iter()
+            .collect();
+        log::info!("pkg-config found {} on {}", config, library_dir.display());
+        Self::from_dirs(config, include_dir, library_dir, &openmp_dirs)
     }
 
     /// Seek MKL libraries in the given directory.
     ///
-    /// - This will seek the directory recursively until finding MKL libraries,
-    ///   but do not follow symbolic links.
-    /// - This will not seek directory named `ia32*`
+    /// - This will seek the directory recursively in the order of names until all files required for the config are found,
+    ///   but do not follow symbolic links. Versioned directories in the same directory, e.g. `mkl/2023.0.0` and `mkl/2021.1.1`,
+    ///   are seeked from the newest one, and thus the newest installation is found.
+    /// - If several directories found until then contain MKL, the best one is chosen deterministically,
+    ///   see [Self::seek_directory_with].
+    /// - This will not seek directories matching [crate::DEFAULT_IGNORE_PATTERNS], e.g. `ia32*`
+    /// - Libraries whose ELF class or machine does not match to the build target are skipped,
+    ///   see [crate::check_elf_target].
     /// - Retuns `Ok(None)` if `libiomp5.{a,so}` is not found with `mkl-*-*-iomp` configure
     ///   even if MKL binaries are found.
     ///
+    /// Use [Self::seek_directory_with] to limit the depth, to change ignored directories,
+    /// to follow symbolic links, or to rank all installations in the directory tree.
+    ///
     pub fn seek_directory(config: Config, root_dir: impl AsRef<Path>) -> Result<Option<Self>> {
-        let root_dir = root_dir.as_ref();
-        if !root_dir.is_dir() {
-            return Ok(None);
-        }
-        let mut library_dir = None;
-        let mut include_dir = None;
-        let mut iomp5_static_dir = None;
-        let mut iomp5_dynamic_dir = None;
-        for (dir, file_name) in walkdir::WalkDir::new(root_dir)
-            .into_iter()
-            .flatten() // skip unreadable directories
-            .flat_map(|entry| {
-                let path = entry.into_path();
-                // Skip directory
-                if path.is_dir() {
-                    return None;
-                }
-                // Skip files for 32bit system under `ia32*/` and `win-x86`
-                if path.components().any(|c| {
-                    if let std::path::Component::Normal(c) = c {
-                        if let Some(c) = c.to_str() {
-                            if c.starts_with("ia32") || c == "win-x86" {
-                                return true;
-                            }
-                        }
-                    }
-                    false
-                }) {
-                    return None;
-                }
-
-                let dir = path
-                    .parent()
-                    .expect("parent must exist here since this is under `root_dir`")
-                    .to_owned();
-
-                if let Some(Some(file_name)) = path.file_name().map(|f| f.to_str()) {
-                    Some((dir, file_name.to_string()))
-                } else {
-                    None
-                }
-            })
-        {
-            if include_dir.is_none() && file_name == "mkl.h" {
-                log::info!("Found mkl.h at {}", dir.display());
-                include_dir = Some(dir);
-                continue;
-            }
-
-            if library_dir.is_none() {
-                for name in mkl_libs(config) {
-                    if file_name == mkl_file_name(config.link, &name) {
-                        log::info!("Found {} at {}", file_name, dir.display());
-                        library_dir = Some(dir.clone());
-                        continue;
-                    }
-                }
-            }
-
-            // Do not seek OpenMP runtime if `Threading::Sequential`
-            if config.parallel == Threading::OpenMP {
-                // Allow both dynamic/static library by default
-                //
-                // This is due to some distribution does not provide libiomp5.a
-                let possible_link_types = if cfg!(feature = "openmp-strict-link-type") {
-                    vec![config.link]
-                } else {
-                    vec![config.link, config.link.otherwise()]
-                };
-                for link in possible_link_types {
-                    if file_name == openmp_runtime_file_name(link) {
-                        match link {
-                            LinkType::Static => {
-                                log::info!(
-                                    "Found static OpenMP runtime ({}): {}",
-                                    file_name,
-                                    dir.display()
-                                );
-                                iomp5_static_dir = Some(dir.clone())
-                            }
-                            LinkType::Dynamic => {
-                                log::info!(
-                                    "Found dynamic OpenMP runtime ({}): {}",
-                                    file_name,
-                                    dir.display()
-                                );
-                                iomp5_dynamic_dir = Some(dir.clone())
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        if config.parallel == Threading::OpenMP
-            && iomp5_dynamic_dir.is_none()
-            && iomp5_static_dir.is_none()
-        {
-            if let Some(ref lib) = library_dir {
-                log::warn!(
-                    "OpenMP runtime not found while MKL found at {}",
-                    lib.display()
-                );
-            }
-            return Ok(None);
-        }
-        Ok(match (library_dir, include_dir) {
-            (Some(library_dir), Some(include_dir)) => Some(Library {
-                config,
-                include_dir,
-                library_dir,
-                iomp5_static_dir,
-                iomp5_dynamic_dir,
-            }),
-            _ => None,
-        })
+        Self::seek_directory_with(config, root_dir, &SeekOptions::default())
     }
 
     /// Seek MKL in system
@@ -328,27 +328,10 @@ impl Library {
     ///   - `/opt/intel` for Linux
     ///   - `C:/Program Files (x86)/IntelSWTools/` and `C:/Program Files (x86)/Intel/oneAPI/` for Windows
     ///
+    /// Use [Discovery] to customize the order and sources.
+    ///
     pub fn new(config: Config) -> Result<Self> {
-        if let Some(lib) = Self::pkg_config(config)? {
-            return Ok(lib);
-        }
-        if let Ok(mklroot) = std::env::var("MKLROOT") {
-            log::info!("MKLROOT environment variable is detected: {}", mklroot);
-            if let Some(lib) = Self::seek_directory(config, mklroot)? {
-                return Ok(lib);
-            }
-        }
-        for path in [
-            "/opt/intel",
-            "C:/Program Files (x86)/IntelSWTools/",
-            "C:/Program Files (x86)/Intel/oneAPI/",
-        ] {
-            let path = Path::new(path);
-            if let Some(lib) = Self::seek_directory(config, path)? {
-                return Ok(lib);
-            }
-        }
-        bail!("Intel MKL not found in system");
+        Discovery::with_defaults(config).find()
     }
 
     pub fn available() -> Vec<Self> {
@@ -378,7 +361,7 @@ impl Library {
         let mut year = None;
         let mut minor = None;
         let mut update = None;
-        for line in f.lines().flatten() {
+        for line in f.lines().map_while(Result::ok) {
             if !line.starts_with("#define") {
                 continue;
             }
@@ -396,29 +379,418 @@ impl Library {
         }
     }
 
+    /// Check that found files belong to a single MKL installation
+    ///
+    /// This returns an error if
+    ///
+    /// - `mkl.h` does not exist in [Self::include_dir]
+    /// - interface (`mkl_intel_lp64` or `mkl_intel_ilp64`), threading, and core libraries
+    ///   do not exist together in [Self::library_dir]
+    /// - OpenMP runtime acceptable by [Self::openmp_link_policy] does not exist
+    ///   for `mkl-*-*-iomp` configs, see [Self::openmp_link_type]
+    /// - the version in `mkl_version.h` or in the path of include directory, e.g. `2019.5.281`,
+    ///   is different from the version in the path of library directory
+    ///
+    /// and returns warnings for less certain cases:
+    ///
+    /// - the include and library directories are not placed under a common installation root
+    /// - the OpenMP runtime is taken from an installation of another version
+    ///
+    pub fn check_consistency(&self) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+
+        if !self.include_dir.join("mkl.h").is_file() {
+            bail!("mkl.h is not found in {}", self.include_dir.display());
+        }
+        let missing = missing_mkl_libs(self.config, &self.library_dir);
+        if !missing.is_empty() {
+            bail!(
+                "{} are not found in {}",
+                missing.join(", "),
+                self.library_dir.display()
+            );
+        }
+
+        if self.config.parallel == Threading::OpenMP && self.openmp_link_type().is_none() {
+            bail!(
+                "OpenMP runtime is not found for {} with {} link policy",
+                self.config,
+                self.openmp_link_policy
+            );
+        }
+
+        let header_year = match self.version() {
+            Ok((year, _, _)) => Some(year),
+            Err(e) => {
+                warnings.push(format!(
+                    "Cannot determine MKL version from {}: {}",
+                    self.include_dir.display(),
+                    e
+                ));
+                None
+            }
+        };
+        let lib_version = version_in_path(&self.library_dir);
+        if let Some(lib_version) = &lib_version {
+            if let Some(header_year) = header_year {
+                if version_year(lib_version) != Some(header_year) {
+                    bail!(
+                        "mkl_version.h in {} is for {}, but libraries in {} are for {}",
+                        self.include_dir.display(),
+                        header_year,
+                        self.library_dir.display(),
+                        lib_version
+                    );
+                }
+            }
+            if let Some(include_version) = version_in_path(&self.include_dir) {
+                if version_year(&include_version) != version_year(lib_version) {
+                    bail!(
+                        "Headers in {} ({}) and libraries in {} ({}) have different versions",
+                        self.include_dir.display(),
+                        include_version,
+                        self.library_dir.display(),
+                        lib_version
+                    );
+                }
+            }
+        }
+
+        match (
+            installation_root(&self.include_dir, &["include"]),
+            installation_root(&self.library_dir, &["lib", "lib64"]),
+        ) {
+            (Some(inc), Some(lib)) if inc == lib => {}
+            _ => warnings.push(format!(
+                "Headers in {} and libraries in {} may belong to different installations",
+                self.include_dir.display(),
+                self.library_dir.display()
+            )),
+        }
+
+        for dir in self
+            .iomp5_static_dir
+            .iter()
+            .chain(self.iomp5_dynamic_dir.iter())
+        {
+            if let (Some(omp), Some(mkl)) = (version_in_path(dir), &lib_version) {
+                if version_year(&omp) != version_year(mkl) {
+                    warnings.push(format!(
+                        "OpenMP runtime in {} ({}) is taken from another version than MKL ({})",
+                        dir.display(),
+                        omp,
+                        mkl
+                    ));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Library files used for [Self::config]
+    ///
+    /// - MKL libraries listed in [mkl_libs]
+    /// - CPU-dispatch libraries listed in [mkl_dyn_libs] which exist in [Self::library_dir]
+    /// - OpenMP runtime chosen by [Self::openmp_link_type] for `mkl-*-*-iomp`
+    ///
+    /// For shared libraries, versioned files, e.g. `libmkl_core.so.2`, are also listed
+    /// since executables refer them instead of `libmkl_core.so`.
+    pub fn library_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for name in mkl_libs(self.config)
+            .into_iter()
+            .chain(mkl_dyn_libs(self.config))
+        {
+            files.extend(existing_files(
+                &self.library_dir,
+                &mkl_file_name(self.config.link, &name),
+            ));
+        }
+        if let Some((link, dir)) = self.openmp_runtime_dir() {
+            files.extend(existing_files(dir, &self.openmp_runtime.file_name(link)));
+        }
+        files
+    }
+
+    /// Link type of OpenMP runtime chosen by [Self::openmp_link_policy]
+    ///
+    /// Returns `None` for `mkl-*-*-seq`, or if no acceptable runtime is found.
+    pub fn openmp_link_type(&self) -> Option<LinkType> {
+        self.openmp_runtime_dir().map(|(link, _dir)| link)
+    }
+
+    /// Link type and directory of OpenMP runtime chosen by [Self::openmp_link_policy]
+    pub(crate) fn openmp_runtime_dir(&self) -> Option<(LinkType, &Path)> {
+        if self.config.parallel != Threading::OpenMP {
+            return None;
+        }
+        self.openmp_link_policy
+            .link_types(self.config)
+            .into_iter()
+            .find_map(|link| {
+                let dir = match link {
+                    LinkType::Static => self.iomp5_static_dir.as_deref()?,
+                    LinkType::Dynamic => self.iomp5_dynamic_dir.as_deref()?,
+                };
+                if dir.join(self.openmp_runtime.file_name(link)).is_file() {
+                    Some((link, dir))
+                } else {
+                    None
+                }
+            })
+    }
+
     /// Print `cargo:rustc-link-*` metadata to stdout
     pub fn print_cargo_metadata(&self) -> Result<()> {
-        println!("cargo:rerun-if-env-changed=MKLROOT");
-        println!("cargo:rustc-link-search={}", self.library_dir.display());
+        for line in self.cargo_metadata() {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Lines printed by [Self::print_cargo_metadata]
+    pub(crate) fn cargo_metadata(&self) -> Vec<String> {
+        let mut lines = vec![
+            "cargo:rerun-if-env-changed=MKLROOT".to_string(),
+            format!("cargo:rerun-if-env-changed={}", OPENMP_LINK_POLICY_ENV),
+            format!("cargo:rerun-if-env-changed={}", ALLOW_LIBOMP_ENV),
+            format!("cargo:rustc-link-search={}", self.library_dir.display()),
+        ];
         for lib in mkl_libs(self.config) {
             match self.config.link {
                 LinkType::Static => {
-                    println!("cargo:rustc-link-lib=static={}", lib);
+                    lines.push(format!("cargo:rustc-link-lib=static={}", lib));
                 }
                 LinkType::Dynamic => {
-                    println!("cargo:rustc-link-lib=dylib={}", lib);
+                    lines.push(format!("cargo:rustc-link-lib=dylib={}", lib));
                 }
             }
         }
 
-        if self.config.parallel == Threading::OpenMP {
-            if let Some(ref dir) = self.iomp5_static_dir {
-                println!("cargo:rustc-link-search={}", dir.display());
-            }
-            if let Some(ref dir) = self.iomp5_dynamic_dir {
-                println!("cargo:rustc-link-search={}", dir.display());
-            }
-            println!("cargo:rustc-link-lib={}", OPENMP_RUNTIME_LIB);
+        if let Some((link, dir)) = self.openmp_runtime_dir() {
+            lines.push(format!("cargo:rustc-link-search={}", dir.display()));
+            // `libiomp5md.lib` on Windows is an import library of `libiomp5md.dll`, as is `libomp.lib`
+            let kind = match link {
+                LinkType::Static if !cfg!(target_os = "windows") => "static",
+                _ => "dylib",
+            };
+            lines.push(format!(
+                "cargo:rustc-link-lib={}={}",
+                kind,
+                self.openmp_runtime.lib_name()
+            ));
+        }
+        lines
+    }
+}
+
+/// `dir/file_name` and versioned files like `dir/file_name.2` which exist
+pub(crate) fn existing_files(dir: &Path, file_name: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let path = dir.join(file_name);
+    if path.is_file() {
+        files.push(path);
+    }
+    let prefix = format!("{}.", file_name);
+    if let Ok(entries) = fs::read_dir(dir) {
+        let mut versioned: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |name| name.starts_with(&prefix))
+            })
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        versioned.sort();
+        files.extend(versioned);
+    }
+    files
+}
+
+/// MKL libraries for `cfg` which do not exist in `dir`
+pub(crate) fn missing_mkl_libs(cfg: Config, dir: &Path) -> Vec<String> {
+    mkl_libs(cfg)
+        .into_iter()
+        .map(|name| mkl_file_name(cfg.link, &name))
+        .filter(|file_name| !dir.join(file_name).is_file())
+        .collect()
+}
+
+/// Version string embedded in a path, e.g. `2023.0.0` in `/opt/intel/oneapi/mkl/2023.0.0/lib/intel64`
+/// or `2020.1.217` in `/opt/intel/compilers_and_libraries_2020.1.217/linux/mkl`.
+///
+/// Only MKL-specific components are used, and the one nearest to the leaf is taken:
+///
+/// - `mkl/{version}` and `compiler/{version}` of oneAPI
+/// - `compilers_and_libraries_{version}` of Parallel Studio XE
+/// - `mkl_*-{version}.data` of unpacked pip wheels
+///
+/// Other year-like numbers, e.g. `py2023` of a conda environment, are not versions of MKL.
+pub(crate) fn version_in_path(path: &Path) -> Option<String> {
+    let names: Vec<&str> = path
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .collect();
+    (0..names.len())
+        .rev()
+        .find_map(|i| version_in_dir_name(i.checked_sub(1).map(|p| names[p]), names[i]))
+}
+
+/// Version of MKL in the directory `name` placed in `parent`, see [version_in_path]
+pub(crate) fn version_in_dir_name(parent: Option<&str>, name: &str) -> Option<String> {
+    if parent == Some("mkl") || parent == Some("compiler") {
+        return as_version(name);
+    }
+    if let Some(version) = name.strip_prefix("compilers_and_libraries_") {
+        return as_version(version);
+    }
+    if name.starts_with("mkl") {
+        if let Some(package) = name.strip_suffix(".data") {
+            return as_version(package.rsplit('-').next()?);
+        }
+    }
+    None
+}
+
+/// `input` if it is a version string like `2023.0.0`
+fn as_version(input: &str) -> Option<String> {
+    if !input.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    version_year(input).map(|_| input.to_string())
+}
+
+/// Leading year of a version string like `2023.0.0`
+fn version_year(version: &str) -> Option<u32> {
+    let year = version.split('.').next()?;
+    if year.len() != 4 {
+        return None;
+    }
+    let year: u32 = year.parse().ok()?;
+    if (2000..2100).contains(&year) {
+        Some(year)
+    } else {
+        None
+    }
+}
+
+/// Parent of the nearest ancestor named one of `names`, e.g. `/opt/intel/mkl` for `/opt/intel/mkl/lib/intel64`
+pub(crate) fn installation_root(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|p| {
+            p.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| names.contains(&name))
+        })
+        .and_then(|p| p.parent())
+        .map(|p| p.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_os = "linux")]
+    use crate::testing::*;
+
+    #[test]
+    fn version_from_path() {
+        assert_eq!(
+            version_in_path(Path::new("/opt/intel/oneapi/mkl/2023.0.0/lib/intel64")).as_deref(),
+            Some("2023.0.0")
+        );
+        assert_eq!(
+            version_in_path(Path::new(
+                "/opt/intel/compilers_and_libraries_2020.1.217/linux/mkl/lib/intel64_lin"
+            ))
+            .as_deref(),
+            Some("2020.1.217")
+        );
+        assert_eq!(
+            version_in_path(Path::new("/usr/lib/x86_64-linux-gnu")),
+            None
+        );
+        assert_eq!(
+            version_in_path(Path::new("/opt/intel/mkl/lib/intel64")),
+            None
+        );
+        assert_eq!(
+            version_in_path(Path::new("/tmp/mkl_devel-2023.1.0.data/data/lib")).as_deref(),
+            Some("2023.1.0")
+        );
+        // Year-like numbers not related to MKL
+        assert_eq!(
+            version_in_path(Path::new("/opt/conda/envs/py2023/lib")),
+            None
+        );
+        assert_eq!(
+            version_in_path(Path::new("/home/user/2019-proj/mkl/lib")),
+            None
+        );
+        assert_eq!(
+            version_in_path(Path::new("/home/user/mkl/latest/lib")),
+            None
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unrelated_year_in_path() -> Result<()> {
+        let parent = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let mkl = FakeMkl::new_in(&parent.root().join("py2023"), Layout::Conda, (2024, 0, 0))?;
+        let cfg = Config::from_str("mkl-static-lp64-seq")?;
+        let lib = Library::from_dirs(cfg, mkl.include_dir(), mkl.library_dir(), &[])?.unwrap();
+        assert_eq!(lib.version()?, (2024, 0, 0));
+        assert!(lib.check_consistency()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn root_of_installation() {
+        assert_eq!(
+            installation_root(Path::new("/opt/intel/mkl/lib/intel64"), &["lib"]),
+            Some(PathBuf::from("/opt/intel/mkl"))
+        );
+        assert_eq!(
+            installation_root(Path::new("/usr/include/mkl"), &["include"]),
+            Some(PathBuf::from("/usr"))
+        );
+        assert_eq!(installation_root(Path::new("/opt/mkl"), &["lib"]), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cargo_metadata() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let lib = mkl.seek("mkl-static-ilp64-iomp")?;
+        let lib_dir = mkl.library_dir().display().to_string();
+        let omp_dir = mkl.openmp_dir().display().to_string();
+        let expected = vec![
+            "cargo:rerun-if-env-changed=MKLROOT".to_string(),
+            "cargo:rerun-if-env-changed=INTEL_MKL_OPENMP_LINK".to_string(),
+            "cargo:rerun-if-env-changed=INTEL_MKL_ALLOW_LIBOMP".to_string(),
+            format!("cargo:rustc-link-search={}", lib_dir),
+            "cargo:rustc-link-lib=static=mkl_intel_ilp64".to_string(),
+            "cargo:rustc-link-lib=static=mkl_intel_thread".to_string(),
+            "cargo:rustc-link-lib=static=mkl_core".to_string(),
+            format!("cargo:rustc-link-search={}", omp_dir),
+            "cargo:rustc-link-lib=static=iomp5".to_string(),
+        ];
+        assert_eq!(lib.cargo_metadata(), expected);
+
+        // Dynamic runtime in the same directory is chosen by the policy
+        for policy in [OpenMpLinkPolicy::StrictDynamic, OpenMpLinkPolicy::Any] {
+            let lib = Library {
+                openmp_link_policy: policy,
+                ..lib.clone()
+            };
+            assert_eq!(lib.openmp_link_type(), Some(LinkType::Dynamic));
+            assert_eq!(
+                lib.cargo_metadata().last().unwrap(),
+                "cargo:rustc-link-lib=dylib=iomp5"
+            );
         }
         Ok(())
     }
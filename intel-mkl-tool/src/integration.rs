@@ -0,0 +1,171 @@
+//! Helpers to compile C code or generate bindings against found MKL
+
+use crate::{DataModel, Library};
+
+impl Library {
+    /// C preprocessor definitions required to use MKL headers with [Library::config]
+    ///
+    /// `MKL_ILP64` is defined for `mkl-*-ilp64-*` configs to make `MKL_INT` 64-bit.
+    pub fn defines(&self) -> Vec<(&'static str, Option<&'static str>)> {
+        match self.config.index_size {
+            DataModel::LP64 => Vec::new(),
+            DataModel::ILP64 => vec![("MKL_ILP64", None)],
+        }
+    }
+
+    /// Add include directory and [Library::defines] to `cc::Build`
+    ///
+    /// ```no_run
+    /// // build.rs of a crate compiling C code calling MKL
+    /// use intel_mkl_tool::{Config, Library};
+    /// use std::str::FromStr;
+    ///
+    /// let lib = Library::new(Config::from_str("mkl-static-ilp64-seq").unwrap()).unwrap();
+    /// let mut build = cc::Build::new();
+    /// lib.configure_cc(&mut build);
+    /// build.file("src/shim.c").compile("shim");
+    /// ```
+    #[cfg(feature = "cc")]
+    pub fn configure_cc<'a>(&self, build: &'a mut cc::Build) -> &'a mut cc::Build {
+        build.include(&self.include_dir);
+        for (key, value) in self.defines() {
+            build.define(key, value);
+        }
+        build
+    }
+
+    /// `bindgen::Builder` with the same conventions as `intel-mkl-sys/bindgen.sh`
+    ///
+    /// - Generate `core` based bindings
+    /// - Derive `Default`, `Eq`, `Hash`, and `Ord` if possible
+    /// - Only items defined in the headers under [Library::include_dir] are generated
+    ///
+    /// and include directory and [Library::defines] are passed to clang.
+    ///
+    /// ```no_run
+    /// use intel_mkl_tool::{Config, Library};
+    /// use std::str::FromStr;
+    ///
+    /// let lib = Library::new(Config::from_str("mkl-static-lp64-seq").unwrap()).unwrap();
+    /// let bindings = lib
+    ///     .bindgen_builder()
+    ///     .header_contents("wrapper.h", "#include <mkl_dfti.h>")
+    ///     .generate()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "bindgen")]
+    pub fn bindgen_builder(&self) -> bindgen::Builder {
+        let mut builder = bindgen::Builder::default()
+            .use_core()
+            .derive_default(true)
+            .derive_eq(true)
+            .derive_hash(true)
+            .derive_ord(true)
+            .clang_arg(format!("-I{}", self.include_dir.display()))
+            .allowlist_file(format!(
+                "{}/.*",
+                escape_regex(&self.include_dir.display().to_string())
+            ));
+        for (key, value) in self.defines() {
+            builder = builder.clang_arg(match value {
+                Some(value) => format!("-D{}={}", key, value),
+                None => format!("-D{}", key),
+            });
+        }
+        builder
+    }
+}
+
+/// Escape a path to match literally in regex used by `bindgen::Builder::allowlist_file`
+#[cfg(feature = "bindgen")]
+fn escape_regex(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{testing::*, Config};
+    use anyhow::Result;
+
+    fn seek_all() -> Result<(FakeMkl, Vec<Library>)> {
+        let mkl = FakeMkl::new(Layout::OneApi, (2023, 0, 0))?;
+        let mut libs = Vec::new();
+        for cfg in Config::possibles() {
+            libs.push(Library::seek_directory(cfg, mkl.root())?.unwrap());
+        }
+        Ok((mkl, libs))
+    }
+
+    #[test]
+    fn defines() -> Result<()> {
+        let (_mkl, libs) = seek_all()?;
+        assert_eq!(libs.len(), 8);
+        for lib in libs {
+            let expected = match lib.config.index_size {
+                DataModel::LP64 => Vec::new(),
+                DataModel::ILP64 => vec![("MKL_ILP64", None)],
+            };
+            assert_eq!(lib.defines(), expected, "{}", lib.config);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cc")]
+    #[test]
+    fn configure_cc() -> Result<()> {
+        let (mkl, libs) = seek_all()?;
+        for lib in libs {
+            let mut build = cc::Build::new();
+            build
+                .cargo_metadata(false)
+                .target("x86_64-unknown-linux-gnu")
+                .host("x86_64-unknown-linux-gnu")
+                .opt_level(0);
+            lib.configure_cc(&mut build);
+            let args: Vec<String> = build
+                .get_compiler()
+                .args()
+                .iter()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect();
+            let include = mkl.include_dir().display().to_string();
+            assert!(
+                args.windows(2)
+                    .any(|pair| pair[0] == "-I" && pair[1] == include),
+                "{:?}",
+                args
+            );
+            assert_eq!(
+                args.iter().any(|arg| arg == "-DMKL_ILP64"),
+                lib.config.index_size == DataModel::ILP64,
+                "{}: {:?}",
+                lib.config,
+                args
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "bindgen")]
+    #[test]
+    fn escape_regex_path() {
+        assert_eq!(escape_regex("/opt/intel/mkl"), "/opt/intel/mkl");
+        assert_eq!(
+            escape_regex("/opt/mkl-2023.0/include"),
+            r"/opt/mkl\-2023\.0/include"
+        );
+        assert_eq!(escape_regex("/home/c++/mkl"), r"/home/c\+\+/mkl");
+        assert_eq!(
+            escape_regex(r"C:\Program Files (x86)\mkl"),
+            r"C:\\Program Files \(x86\)\\mkl"
+        );
+    }
+}
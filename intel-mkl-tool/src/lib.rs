@@ -4,8 +4,50 @@
 //! of Intel MKL installed in user system.
 //!
 
+#[cfg(feature = "archive")]
+mod archive;
+mod cmake;
 mod config;
+#[cfg(any(feature = "archive", feature = "sbom"))]
+mod digest;
+mod discovery;
+mod elf;
 mod entry;
+mod integration;
+#[cfg(feature = "lock")]
+mod lock;
+mod metadata;
+#[cfg(feature = "ocipkg")]
+mod package;
+mod pkgconfig;
+mod probe;
+mod runtime;
+#[cfg(feature = "sbom")]
+mod sbom;
+#[cfg(feature = "serde")]
+mod schema;
+mod seek;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
+#[cfg(feature = "archive")]
+pub use archive::*;
+pub use cmake::*;
 pub use config::*;
+#[cfg(any(feature = "archive", feature = "sbom"))]
+pub use digest::*;
+pub use discovery::*;
+pub use elf::*;
 pub use entry::*;
+#[cfg(feature = "lock")]
+pub use lock::*;
+pub use metadata::*;
+#[cfg(feature = "ocipkg")]
+pub use package::*;
+pub use pkgconfig::*;
+pub use runtime::*;
+#[cfg(feature = "sbom")]
+pub use sbom::*;
+#[cfg(feature = "serde")]
+pub use schema::*;
+pub use seek::*;
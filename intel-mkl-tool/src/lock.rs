@@ -0,0 +1,268 @@
+//! Lockfile pinning MKL version and file digests, enabled by `lock` feature
+
+use crate::{Config, FileKind, Library};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, fs, path::Path, str::FromStr};
+
+/// Environment variable to the lockfile verified in `build.rs`, e.g. `INTEL_MKL_LOCK=/path/to/mkl.lock`
+pub const LOCK_ENV: &str = "INTEL_MKL_LOCK";
+
+/// Environment variable to choose [LockPolicy]
+pub const LOCK_POLICY_ENV: &str = "INTEL_MKL_LOCK_POLICY";
+
+/// Conventional name of the lockfile
+pub const LOCK_FILE_NAME: &str = "mkl.lock";
+
+/// Version of the lockfile format
+pub const LOCK_FORMAT_VERSION: u32 = 1;
+
+/// What to do when found MKL does not match the lockfile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Fail to build (default)
+    Deny,
+    /// Continue to build with warnings
+    Warn,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        LockPolicy::Deny
+    }
+}
+
+impl fmt::Display for LockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockPolicy::Deny => write!(f, "deny"),
+            LockPolicy::Warn => write!(f, "warn"),
+        }
+    }
+}
+
+impl FromStr for LockPolicy {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "deny" => LockPolicy::Deny,
+            "warn" => LockPolicy::Warn,
+            another => bail!("Invalid lock policy: {}", another),
+        })
+    }
+}
+
+impl LockPolicy {
+    /// Read [LOCK_POLICY_ENV], or default if it is not set
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(LOCK_POLICY_ENV) {
+            Ok(policy) if !policy.is_empty() => Self::from_str(&policy),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// File recorded in [MklLock]
+///
+/// Only the file name is recorded since MKL may be installed in different directories on each machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Content of `mkl.lock`
+///
+/// ```json
+/// {
+///   "lock_version": 1,
+///   "config": "mkl-static-lp64-seq",
+///   "version": "2020.0.1",
+///   "files": [
+///     { "name": "libmkl_core.a", "size": 123456, "sha256": "..." }
+///   ]
+/// }
+/// ```
+///
+/// `config` is serialized in the same way as [Library::to_json].
+/// This is written by `intel-mkl-tool lock` command, and verified in `intel-mkl-src/build.rs` if [LOCK_ENV] is set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MklLockRepr", into = "MklLockRepr")]
+pub struct MklLock {
+    pub config: Config,
+    pub version: Option<(u32, u32, u32)>,
+    pub files: Vec<LockedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MklLockRepr {
+    lock_version: u32,
+    config: Config,
+    version: Option<String>,
+    files: Vec<LockedFile>,
+}
+
+impl From<MklLock> for MklLockRepr {
+    fn from(lock: MklLock) -> Self {
+        MklLockRepr {
+            lock_version: LOCK_FORMAT_VERSION,
+            config: lock.config,
+            version: lock
+                .version
+                .map(|(year, minor, update)| format!("{}.{}.{}", year, minor, update)),
+            files: lock.files,
+        }
+    }
+}
+
+impl TryFrom<MklLockRepr> for MklLock {
+    type Error = anyhow::Error;
+    fn try_from(repr: MklLockRepr) -> Result<Self> {
+        if repr.lock_version != LOCK_FORMAT_VERSION {
+            bail!(
+                "Unsupported lock version {}, expected {}",
+                repr.lock_version,
+                LOCK_FORMAT_VERSION
+            );
+        }
+        let version = match repr.version {
+            Some(version) => {
+                let parts: Vec<u32> = version
+                    .split('.')
+                    .map(u32::from_str)
+                    .collect::<Result<_, _>>()
+                    .with_context(|| format!("Invalid version: {}", version))?;
+                match parts[..] {
+                    [year, minor, update] => Some((year, minor, update)),
+                    _ => bail!("Invalid version: {}", version),
+                }
+            }
+            None => None,
+        };
+        Ok(MklLock {
+            config: repr.config,
+            version,
+            files: repr.files,
+        })
+    }
+}
+
+impl MklLock {
+    /// Record [Library::version] and [Library::files]
+    ///
+    /// OpenMP runtime is not recorded since it is distributed separately from MKL, e.g. with compilers.
+    pub fn from_library(lib: &Library) -> Result<Self> {
+        let files = lib
+            .files()?
+            .into_iter()
+            .filter(|file| file.kind != FileKind::OpenMpRuntime)
+            .map(|file| LockedFile {
+                name: file
+                    .path
+                    .file_name()
+                    .map_or_else(String::new, |name| name.to_string_lossy().to_string()),
+                size: file.size,
+                sha256: file.sha256,
+            })
+            .collect();
+        Ok(MklLock {
+            config: lib.config,
+            version: lib.version().ok(),
+            files,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    pub fn from_json(input: &str) -> Result<Self> {
+        Ok(serde_json::from_str(input)?)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let input = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lockfile {}", path.display()))?;
+        Self::from_json(&input).with_context(|| format!("Invalid lockfile {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("Failed to write lockfile {}", path.display()))
+    }
+
+    /// Differences of `lib` from this lock, empty if it matches
+    pub fn mismatches(&self, lib: &Library) -> Result<Vec<String>> {
+        let found = Self::from_library(lib)?;
+        let mut mismatches = Vec::new();
+        if found.config != self.config {
+            mismatches.push(format!(
+                "config is {} while {} is locked",
+                found.config, self.config
+            ));
+        }
+        if found.version != self.version {
+            let show = |version: Option<(u32, u32, u32)>| {
+                version.map_or("unknown".to_string(), |(year, minor, update)| {
+                    format!("{}.{}.{}", year, minor, update)
+                })
+            };
+            mismatches.push(format!(
+                "version is {} while {} is locked",
+                show(found.version),
+                show(self.version)
+            ));
+        }
+        for locked in &self.files {
+            match found.files.iter().find(|file| file.name == locked.name) {
+                Some(file) if file.sha256 != locked.sha256 => mismatches.push(format!(
+                    "SHA-256 of {} is {} while {} is locked",
+                    file.name, file.sha256, locked.sha256
+                )),
+                Some(_) => {}
+                None => mismatches.push(format!("{} is not found", locked.name)),
+            }
+        }
+        for file in &found.files {
+            if !self.files.iter().any(|locked| locked.name == file.name) {
+                mismatches.push(format!("{} is not locked", file.name));
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn lock_roundtrip() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let lib = mkl.seek("mkl-static-lp64-iomp")?;
+        let cfg = lib.config;
+        let lock = MklLock::from_library(&lib)?;
+        assert_eq!(lock.version, Some((2020, 0, 1)));
+        assert_eq!(lock.files.len(), 3);
+        assert_eq!(MklLock::from_json(&lock.to_json()?)?, lock);
+        assert!(lock.mismatches(&lib)?.is_empty());
+
+        let json = lock
+            .to_json()?
+            .replace("\"lock_version\": 1", "\"lock_version\": 2");
+        let err = MklLock::from_json(&json).unwrap_err();
+        assert!(err.to_string().contains("lock version"), "{}", err);
+
+        // Another installation of different version and contents
+        let other = FakeMkl::new(Layout::Classic, (2020, 0, 2))?;
+        fs::write(other.library_dir().join("libmkl_core.a"), "!<arch>\n\n")?;
+        let other = Library::seek_directory(cfg, other.root())?.unwrap();
+        let mismatches = lock.mismatches(&other)?;
+        assert_eq!(mismatches.len(), 2, "{:?}", mismatches);
+        assert!(mismatches[0].starts_with("version is 2020.0.2"));
+        assert!(mismatches[1].starts_with("SHA-256 of libmkl_core.a"));
+        Ok(())
+    }
+}
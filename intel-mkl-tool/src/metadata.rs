@@ -0,0 +1,144 @@
+//! Links metadata exported by `intel-mkl-src`
+
+use crate::{entry::installation_root, Config, DataModel, Library, LinkType, Threading};
+use anyhow::{Context, Result};
+use std::{env, path::PathBuf, str::FromStr};
+
+/// Names of `cfg` set by [LinksMetadata::print_cargo_cfg]
+pub const CFG_NAMES: &[&str] = &[
+    "mkl_static",
+    "mkl_dynamic",
+    "mkl_lp64",
+    "mkl_ilp64",
+    "mkl_iomp",
+    "mkl_seq",
+];
+
+impl Config {
+    /// Print `cargo:config`, `cargo:link`, `cargo:data_model`, and `cargo:threading` links metadata
+    pub fn print_links_metadata(&self) {
+        println!("cargo:config={}", self);
+        println!("cargo:link={}", self.link);
+        println!("cargo:data_model={}", self.index_size);
+        println!("cargo:threading={}", self.parallel);
+    }
+
+    /// `cfg` names corresponding to this config, e.g. `["mkl_static", "mkl_lp64", "mkl_seq"]`
+    pub fn cfg_names(&self) -> Vec<&'static str> {
+        vec![
+            match self.link {
+                LinkType::Static => "mkl_static",
+                LinkType::Dynamic => "mkl_dynamic",
+            },
+            match self.index_size {
+                DataModel::LP64 => "mkl_lp64",
+                DataModel::ILP64 => "mkl_ilp64",
+            },
+            match self.parallel {
+                Threading::OpenMP => "mkl_iomp",
+                Threading::Sequential => "mkl_seq",
+            },
+        ]
+    }
+}
+
+impl Library {
+    /// Root directory of MKL installation, e.g. `/opt/intel/mkl`
+    ///
+    /// This is the parent of `include` directory where `mkl.h` exists.
+    pub fn root_dir(&self) -> Option<PathBuf> {
+        installation_root(&self.include_dir, &["include"])
+    }
+
+    /// Print links metadata to stdout, see [LinksMetadata] for keys
+    pub fn print_links_metadata(&self) {
+        self.config.print_links_metadata();
+        if let Some(root) = self.root_dir() {
+            println!("cargo:root={}", root.display());
+        }
+        println!("cargo:include={}", self.include_dir.display());
+        println!("cargo:lib={}", self.library_dir.display());
+        match self.version() {
+            Ok((year, minor, update)) => println!("cargo:version={}.{}.{}", year, minor, update),
+            Err(e) => log::warn!("MKL version is not exported: {}", e),
+        }
+    }
+}
+
+/// Links metadata of `intel-mkl-src` read in `build.rs` of dependent crates
+///
+/// Since `intel-mkl-src` declares `links = "mkl_core"`,
+/// `cargo:KEY=VALUE` lines printed in its `build.rs` are passed to
+/// build scripts of crates depending on `intel-mkl-src` directly
+/// as `DEP_MKL_CORE_KEY` environment variables:
+///
+/// | Environment variable            | Value                                        |
+/// |:--------------------------------|:---------------------------------------------|
+/// | `DEP_MKL_CORE_CONFIG`           | Config name, e.g. `mkl-static-lp64-seq`      |
+/// | `DEP_MKL_CORE_REQUESTED_CONFIG` | Config requested by cargo feature            |
+/// | `DEP_MKL_CORE_LINK`             | `static` or `dynamic`                        |
+/// | `DEP_MKL_CORE_DATA_MODEL`       | `lp64` or `ilp64`                            |
+/// | `DEP_MKL_CORE_THREADING`        | `iomp` or `seq`                              |
+/// | `DEP_MKL_CORE_ROOT`             | Root directory of MKL installation           |
+/// | `DEP_MKL_CORE_INCLUDE`          | Directory where `mkl.h` exists               |
+/// | `DEP_MKL_CORE_LIB`              | Directory where `libmkl_core.*` exists       |
+/// | `DEP_MKL_CORE_VERSION`          | MKL version, e.g. `2020.0.1`                 |
+/// | `DEP_MKL_CORE_BUNDLE_DIR`       | Shared libraries copied from container image |
+///
+/// `REQUESTED_CONFIG` differs from `CONFIG` only when another config is chosen by `INTEL_MKL_FALLBACK`,
+/// see [crate::Fallback].
+/// `ROOT`, `INCLUDE`, and `VERSION` are not set if they are unknown,
+/// e.g. when MKL is downloaded from ghcr.io since the container does not contain headers.
+/// `BUNDLE_DIR` is set only for `mkl-dynamic-*-*` configs linked from the container image,
+/// and executables out of `cargo run` and `cargo test` need it in their rpath or next to them.
+///
+/// ```no_run
+/// // build.rs of a crate depending on intel-mkl-src
+/// use intel_mkl_tool::LinksMetadata;
+///
+/// let mkl = LinksMetadata::from_env().unwrap();
+/// // Enables `#[cfg(mkl_ilp64)]` and so on in this crate
+/// mkl.print_cargo_cfg();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinksMetadata {
+    pub config: Config,
+    pub requested_config: Config,
+    pub root: Option<PathBuf>,
+    pub include: Option<PathBuf>,
+    pub lib: Option<PathBuf>,
+    pub version: Option<String>,
+    pub bundle_dir: Option<PathBuf>,
+}
+
+impl LinksMetadata {
+    /// Read `DEP_MKL_CORE_*` environment variables
+    pub fn from_env() -> Result<Self> {
+        let config = env::var("DEP_MKL_CORE_CONFIG")
+            .context("DEP_MKL_CORE_CONFIG is not set. Does this crate depend on intel-mkl-src?")?;
+        let config = Config::from_str(&config)?;
+        let requested_config = match env::var("DEP_MKL_CORE_REQUESTED_CONFIG") {
+            Ok(requested) => Config::from_str(&requested)?,
+            Err(_) => config,
+        };
+        let path = |key: &str| env::var_os(key).map(PathBuf::from);
+        Ok(LinksMetadata {
+            config,
+            requested_config,
+            root: path("DEP_MKL_CORE_ROOT"),
+            include: path("DEP_MKL_CORE_INCLUDE"),
+            lib: path("DEP_MKL_CORE_LIB"),
+            version: env::var("DEP_MKL_CORE_VERSION").ok(),
+            bundle_dir: path("DEP_MKL_CORE_BUNDLE_DIR"),
+        })
+    }
+
+    /// Print `cargo:rustc-cfg` for the linked config, e.g. `mkl_ilp64`,
+    /// and `cargo:rustc-check-cfg` for all of [CFG_NAMES]
+    pub fn print_cargo_cfg(&self) {
+        println!("cargo:rustc-check-cfg=cfg({})", CFG_NAMES.join(", "));
+        for name in self.config.cfg_names() {
+            println!("cargo:rustc-cfg={}", name);
+        }
+    }
+}
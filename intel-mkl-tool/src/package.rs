@@ -0,0 +1,122 @@
+//! Package found MKL into oci-archive, enabled by `ocipkg` feature
+
+use crate::{Config, Library};
+use anyhow::{bail, Context, Result};
+use std::{fs, path::Path};
+
+/// Default name of container image in the same convention as the fallback of `intel-mkl-src`,
+/// e.g. `ghcr.io/rust-math/rust-mkl/linux/mkl-static-lp64-seq:2020.1-3038006115`.
+///
+/// The tag must be `{MKL version}-{build id}` since `intel-mkl-src` reads MKL version from it.
+pub fn image_name(registry: &str, config: Config, tag: &str) -> String {
+    let os = if cfg!(target_os = "windows") {
+        "windows"
+    } else {
+        "linux"
+    };
+    format!("{}/rust-math/rust-mkl/{}/{}:{}", registry, os, config, tag)
+}
+
+impl Library {
+    /// Tag of image packaged from this installation, i.e. `{MKL version}-local`, e.g. `2020.0.1-local`
+    pub fn local_tag(&self) -> Result<String> {
+        let (year, minor, update) = self.version()?;
+        Ok(format!("{}.{}.{}-local", year, minor, update))
+    }
+
+    /// Create an oci-archive containing [Library::library_files]
+    ///
+    /// The files are flattened in a single layer as the images used by `intel-mkl-src`.
+    /// The created archive can be used by `INTEL_MKL_OCIPKG_ARCHIVE` of `intel-mkl-src` directly,
+    /// or pushed into a registry by [push_package].
+    pub fn package(&self, image_name: &str, output: &Path) -> Result<()> {
+        let name = ocipkg::ImageName::parse(image_name)?;
+        let files = self.library_files();
+        if files.is_empty() {
+            bail!("No library files found for {}", self.config);
+        }
+        for file in &files {
+            log::info!("Add {}", file.display());
+        }
+        let f = fs::File::create(output)
+            .with_context(|| format!("Failed to create {}", output.display()))?;
+        let mut builder = ocipkg::image::Builder::new(f);
+        builder.set_name(&name);
+        builder.append_files(&files)?;
+        builder.into_inner()?;
+        log::info!("Create {} as {}", output.display(), name);
+        Ok(())
+    }
+}
+
+/// Push an oci-archive created by [Library::package] into the registry specified by its image name
+///
+/// Registries on `localhost` are accessed by HTTP, which is useful for testing with a local registry.
+pub fn push_package(archive: &Path) -> Result<()> {
+    ocipkg::distribution::push_image(archive)?;
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{mkl_file_name, testing::*};
+    use std::{collections::BTreeSet, path::PathBuf};
+
+    /// File names in the single layer of oci-archive
+    fn layer_files(archive: &Path, dest: &Path) -> Result<(String, BTreeSet<String>)> {
+        let mut f = fs::File::open(archive)?;
+        let mut archive = ocipkg::image::Archive::new(&mut f);
+        let manifests = archive.get_manifests()?;
+        assert_eq!(manifests.len(), 1);
+        let (name, manifest) = &manifests[0];
+        assert_eq!(manifest.layers().len(), 1);
+        archive.unpack_layer(&manifest.layers()[0], dest)?;
+        let files = fs::read_dir(dest)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect::<Result<_>>()?;
+        Ok((name.to_string(), files))
+    }
+
+    fn file_names(files: &[PathBuf]) -> BTreeSet<String> {
+        files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn package() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        for (cfg, openmp) in [
+            ("mkl-static-lp64-iomp", Some("libiomp5.a")),
+            ("mkl-dynamic-ilp64-seq", None),
+        ] {
+            let lib = mkl.seek(cfg)?;
+            let cfg = lib.config;
+            let name = image_name("localhost:5000", cfg, &lib.local_tag()?);
+            assert_eq!(
+                name,
+                format!(
+                    "localhost:5000/rust-math/rust-mkl/linux/{}:2020.0.1-local",
+                    cfg
+                )
+            );
+
+            let output = mkl.root().join(format!("{}.tar", cfg));
+            lib.package(&name, &output)?;
+            let dest = mkl.root().join(format!("{}-layer", cfg));
+            let (packaged, files) = layer_files(&output, &dest)?;
+            assert_eq!(packaged, name);
+
+            let expected = file_names(&lib.library_files());
+            assert_eq!(files, expected);
+            match openmp {
+                Some(openmp) => assert!(expected.contains(openmp)),
+                None => assert!(!expected.iter().any(|file| file.contains("iomp5"))),
+            }
+            assert!(expected.contains(&mkl_file_name(cfg.link, "mkl_core")));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! Generate pkg-config files for MKL installations which do not ship them
+
+use crate::{mkl_file_name, mkl_libs, Config, DataModel, Library, LinkType};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+impl Library {
+    /// Contents of pkg-config file `{config}.pc`, e.g. `mkl-static-lp64-iomp.pc`
+    ///
+    /// This follows the `.pc` files shipped with MKL:
+    ///
+    /// - `prefix` is the common ancestor of include, library and OpenMP runtime directories
+    /// - `libdir`, `includedir` and `omp_libdir` are the directories read by [Library::pkg_config]
+    /// - `Libs` contains MKL libraries grouped by `-Wl,--start-group` for static link, and OpenMP runtime
+    /// - `Libs.private` contains system libraries required by MKL, which is omitted on Windows
+    /// - `Cflags` contains include directory and [Library::defines], e.g. `-DMKL_ILP64`
+    pub fn pkg_config_file(&self) -> String {
+        let openmp = self.openmp_runtime_dir();
+        let mut dirs = vec![self.include_dir.as_path(), self.library_dir.as_path()];
+        if let Some((_, dir)) = openmp {
+            dirs.push(dir);
+        }
+        let prefix = common_ancestor(&dirs);
+
+        let mut vars = vec![
+            format!("prefix={}", prefix.display()),
+            "exec_prefix=${prefix}".to_string(),
+            format!("libdir={}", self.library_dir.display()),
+            format!("includedir={}", self.include_dir.display()),
+        ];
+        if let Some((_, dir)) = openmp {
+            vars.push(format!("omp_libdir={}", dir.display()));
+        }
+
+        let names = mkl_libs(self.config);
+        let mut libs = match self.config.link {
+            LinkType::Static => {
+                let files: Vec<String> = names
+                    .iter()
+                    .map(|name| format!("${{libdir}}/{}", mkl_file_name(LinkType::Static, name)))
+                    .collect();
+                if cfg!(target_os = "macos") {
+                    files
+                } else {
+                    let mut group = vec!["-Wl,--start-group".to_string()];
+                    group.extend(files);
+                    group.push("-Wl,--end-group".to_string());
+                    group
+                }
+            }
+            LinkType::Dynamic => {
+                let mut libs = vec!["-L${libdir}".to_string()];
+                if !cfg!(target_os = "macos") {
+                    libs.push("-Wl,--no-as-needed".to_string());
+                }
+                libs.extend(names.iter().map(|name| format!("-l{}", name)));
+                libs
+            }
+        };
+        if let Some((link, _)) = openmp {
+            match link {
+                LinkType::Static => libs.push(format!(
+                    "${{omp_libdir}}/{}",
+                    self.openmp_runtime.file_name(LinkType::Static)
+                )),
+                LinkType::Dynamic => {
+                    libs.push("-L${omp_libdir}".to_string());
+                    libs.push(format!("-l{}", self.openmp_runtime.lib_name()));
+                }
+            }
+        }
+
+        let mut cflags = vec!["-I${includedir}".to_string()];
+        for (key, value) in self.defines() {
+            cflags.push(match value {
+                Some(value) => format!("-D{}={}", key, value),
+                None => format!("-D{}", key),
+            });
+        }
+
+        let version = match self.version() {
+            Ok((year, minor, update)) => format!("{}.{}.{}", year, minor, update),
+            Err(_) => "unknown".to_string(),
+        };
+        let libs_private = if cfg!(target_os = "windows") {
+            String::new()
+        } else if cfg!(target_os = "macos") {
+            "Libs.private: -lpthread -lm\n".to_string()
+        } else {
+            "Libs.private: -lpthread -lm -ldl\n".to_string()
+        };
+        let data_model = match self.config.index_size {
+            DataModel::LP64 => "LP64",
+            DataModel::ILP64 => "ILP64",
+        };
+        format!(
+            "# Generated by intel-mkl-tool\n\
+             {vars}\n\
+             \n\
+             Name: {config}\n\
+             Description: Intel(R) Math Kernel Library, {link:?} link, {data_model}, {threading:?}\n\
+             Version: {version}\n\
+             Libs: {libs}\n\
+             {libs_private}\
+             Cflags: {cflags}\n",
+            vars = vars.join("\n"),
+            config = self.config,
+            link = self.config.link,
+            data_model = data_model,
+            threading = self.config.parallel,
+            version = version,
+            libs = libs.join(" "),
+            libs_private = libs_private,
+            cflags = cflags.join(" "),
+        )
+    }
+}
+
+/// Write pkg-config files `{config}.pc` for all configs found in `root` into `dest` directory
+///
+/// Returns the paths of written files. Configs not found in `root` are skipped.
+/// Set `PKG_CONFIG_PATH` to `dest` to let [Library::pkg_config] and C/C++ builds use them.
+pub fn write_pkg_config_files(root: &Path, dest: &Path) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dest)?;
+    let mut written = Vec::new();
+    for cfg in Config::possibles() {
+        let lib = match Library::seek_directory(cfg, root)? {
+            Some(lib) => lib,
+            None => {
+                log::info!("{} is not found in {}", cfg, root.display());
+                continue;
+            }
+        };
+        let path = dest.join(format!("{}.pc", cfg));
+        fs::write(&path, lib.pkg_config_file())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        log::info!("Write {}", path.display());
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The longest common ancestor of `dirs`
+fn common_ancestor(dirs: &[&Path]) -> PathBuf {
+    let mut common = dirs[0].to_path_buf();
+    for dir in &dirs[1..] {
+        while !dir.starts_with(&common) {
+            if !common.pop() {
+                break;
+            }
+        }
+    }
+    common
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::process::Command;
+
+    #[test]
+    fn pkg_config_files() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let dest = mkl.root().join("pkgconfig");
+        let written = write_pkg_config_files(mkl.root(), &dest)?;
+        assert_eq!(written.len(), 8);
+
+        let pc = fs::read_to_string(dest.join("mkl-static-ilp64-iomp.pc"))?;
+        let lib = mkl.seek("mkl-static-ilp64-iomp")?;
+        let cfg = lib.config;
+        assert_eq!(pc, lib.pkg_config_file());
+        assert!(pc.contains(&format!(
+            "prefix={}\n",
+            mkl.root().join("opt/intel").display()
+        )));
+        assert!(pc.contains(
+            "Libs: -Wl,--start-group ${libdir}/libmkl_intel_ilp64.a ${libdir}/libmkl_intel_thread.a \
+             ${libdir}/libmkl_core.a -Wl,--end-group ${omp_libdir}/libiomp5.a\n"
+        ));
+        assert!(pc.contains("Libs.private: -lpthread -lm -ldl\n"));
+        assert!(pc.contains("Cflags: -I${includedir} -DMKL_ILP64\n"));
+
+        let pc = fs::read_to_string(dest.join("mkl-dynamic-lp64-seq.pc"))?;
+        assert!(pc.contains(
+            "Libs: -L${libdir} -Wl,--no-as-needed -lmkl_intel_lp64 -lmkl_sequential -lmkl_core\n"
+        ));
+        assert!(pc.contains("Cflags: -I${includedir}\n"));
+
+        // Read by pkg-config command if installed
+        if let Ok(out) = Command::new("pkg-config")
+            .env("PKG_CONFIG_PATH", &dest)
+            .args(["--cflags", "mkl-static-ilp64-iomp"])
+            .output()
+        {
+            let cflags = String::from_utf8(out.stdout)?;
+            assert_eq!(
+                cflags.trim(),
+                format!("-I{} -DMKL_ILP64", mkl.include_dir().display())
+            );
+
+            // Directories read by Library::pkg_config
+            let variable = |name: &str| -> Result<PathBuf> {
+                let out = Command::new("pkg-config")
+                    .env("PKG_CONFIG_PATH", &dest)
+                    .arg(format!("--variable={}", name))
+                    .arg("mkl-static-ilp64-iomp")
+                    .output()?;
+                Ok(PathBuf::from(String::from_utf8(out.stdout)?.trim()))
+            };
+            assert_eq!(variable("libdir")?, mkl.library_dir());
+            assert_eq!(variable("includedir")?, mkl.include_dir());
+            assert_eq!(variable("omp_libdir")?, mkl.openmp_dir());
+            let found = Library::from_dirs(
+                cfg,
+                variable("includedir")?,
+                variable("libdir")?,
+                &[variable("omp_libdir")?],
+            )?;
+            assert_eq!(found, Some(lib));
+        }
+        Ok(())
+    }
+}
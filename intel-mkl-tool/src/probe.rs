@@ -0,0 +1,138 @@
+//! Verify that found MKL can be linked by building a small probe program
+
+use crate::Library;
+use anyhow::{bail, Context, Result};
+use std::{env, ffi::OsString, fs, path::Path, process::Command};
+
+/// Rust program calling `mkl_get_version`, which is defined in `mkl_core`
+const PROBE_SOURCE: &str = r#"
+use std::os::raw::{c_char, c_int};
+
+#[repr(C)]
+struct MKLVersion {
+    major: c_int,
+    minor: c_int,
+    update: c_int,
+    product_status: *const c_char,
+    build: *const c_char,
+    processor: *const c_char,
+    platform: *const c_char,
+}
+
+extern "C" {
+    fn mkl_get_version(version: *mut MKLVersion);
+}
+
+fn main() {
+    let mut version: MKLVersion = unsafe { std::mem::zeroed() };
+    unsafe { mkl_get_version(&mut version) };
+    println!("{}.{}.{}", version.major, version.minor, version.update);
+}
+"#;
+
+/// Lines of error output shown in diagnostics
+const MAX_DIAGNOSTIC_LINES: usize = 20;
+
+impl Library {
+    /// Arguments of `rustc` corresponding to [Library::print_cargo_metadata]
+    pub fn rustc_link_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for line in self.cargo_metadata() {
+            if let Some(dir) = line.strip_prefix("cargo:rustc-link-search=") {
+                args.push("-L".to_string());
+                args.push(dir.to_string());
+            } else if let Some(lib) = line.strip_prefix("cargo:rustc-link-lib=") {
+                args.push("-l".to_string());
+                args.push(lib.to_string());
+            }
+        }
+        args
+    }
+
+    /// Build a probe program calling `mkl_get_version` with the link options of [Library::print_cargo_metadata]
+    ///
+    /// This is expected to be called in `build.rs`. The probe is compiled by `$RUSTC` for `$TARGET`
+    /// using `$RUSTC_LINKER` if set, and placed in `out_dir/mkl-probe`.
+    /// The probe is only linked, not executed, since shared libraries may not be found by the loader at build time.
+    ///
+    /// Returns an error with the linker output if linking fails,
+    /// e.g. libraries are built for another architecture, or some symbols are missing.
+    pub fn try_link(&self, out_dir: &Path) -> Result<()> {
+        let dir = out_dir.join("mkl-probe");
+        fs::create_dir_all(&dir)?;
+        let source = dir.join("mkl_probe.rs");
+        fs::write(&source, PROBE_SOURCE)?;
+
+        let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
+        let mut cmd = Command::new(rustc);
+        cmd.arg("--crate-name")
+            .arg("mkl_probe")
+            .arg("--crate-type")
+            .arg("bin")
+            .arg("--edition=2018")
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&source)
+            .args(self.rustc_link_args());
+        if let Ok(target) = env::var("TARGET") {
+            cmd.arg("--target").arg(target);
+        }
+        if let Some(linker) = env::var_os("RUSTC_LINKER") {
+            let mut arg = OsString::from("linker=");
+            arg.push(linker);
+            cmd.arg("-C").arg(arg);
+        }
+        log::info!(
+            "Try to link MKL in {}: {:?}",
+            self.library_dir.display(),
+            cmd
+        );
+        let output = cmd
+            .output()
+            .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let lines: Vec<&str> = stderr.lines().collect();
+            let tail = &lines[lines.len().saturating_sub(MAX_DIAGNOSTIC_LINES)..];
+            bail!(
+                "Failed to link a probe calling mkl_get_version with `{}`:\n{}",
+                self.rustc_link_args().join(" "),
+                tail.join("\n")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::testing::*;
+
+    #[test]
+    fn stub_library_does_not_link() -> anyhow::Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let lib = mkl.seek("mkl-static-lp64-seq")?;
+        assert_eq!(
+            lib.rustc_link_args(),
+            vec![
+                "-L".to_string(),
+                mkl.library_dir().display().to_string(),
+                "-l".to_string(),
+                "static=mkl_intel_lp64".to_string(),
+                "-l".to_string(),
+                "static=mkl_sequential".to_string(),
+                "-l".to_string(),
+                "static=mkl_core".to_string(),
+            ]
+        );
+        let err = lib.try_link(mkl.root()).unwrap_err();
+        // Empty archives do not define `mkl_get_version`
+        assert!(
+            err.to_string()
+                .starts_with("Failed to link a probe calling mkl_get_version"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+}
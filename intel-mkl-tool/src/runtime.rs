@@ -0,0 +1,512 @@
+//! Check that the runtime loader resolves MKL and OpenMP runtime of a dynamically linked binary
+//!
+//! This simulates the search order of glibc `ld.so` for `DT_NEEDED` entries,
+//! and of `dlopen` for CPU-dispatch libraries which `libmkl_core` loads at runtime.
+//! It is intended to run on the machine where the binary is deployed,
+//! e.g. `intel-mkl-tool doctor ./target/release/app`.
+
+use crate::{mkl_dyn_libs, read_elf_dynamic, Config, ElfDynamic, LinkType};
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Search paths of the runtime loader other than `DT_RPATH` and `DT_RUNPATH`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoaderEnv {
+    /// Directories in `LD_LIBRARY_PATH`
+    pub ld_library_path: Vec<PathBuf>,
+    /// Directories in `/etc/ld.so.conf` and default directories, e.g. `/usr/lib`
+    pub system_dirs: Vec<PathBuf>,
+}
+
+impl LoaderEnv {
+    /// Read `LD_LIBRARY_PATH` and `/etc/ld.so.conf` of current machine
+    pub fn from_env() -> Self {
+        let ld_library_path = std::env::var("LD_LIBRARY_PATH")
+            .map(|paths| {
+                paths
+                    .split([':', ';'])
+                    .filter(|dir| !dir.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut system_dirs = Vec::new();
+        read_ld_so_conf(Path::new("/etc/ld.so.conf"), &mut system_dirs);
+        let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+        for dir in ["/lib", "/usr/lib"] {
+            system_dirs.push(Path::new(dir).join(&multiarch));
+        }
+        for dir in ["/lib64", "/usr/lib64", "/lib", "/usr/lib"] {
+            system_dirs.push(PathBuf::from(dir));
+        }
+        LoaderEnv {
+            ld_library_path,
+            system_dirs,
+        }
+    }
+}
+
+/// Read directories in `ld.so.conf` following `include` directives
+///
+/// Only `*` at the last component is supported in `include` patterns, e.g. `/etc/ld.so.conf.d/*.conf`.
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<PathBuf>) {
+    let conf = match fs::read_to_string(path) {
+        Ok(conf) => conf,
+        Err(_) => return,
+    };
+    for line in conf.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let pattern = match line.strip_prefix("include") {
+            Some(pattern) => pattern.trim(),
+            None => {
+                dirs.push(PathBuf::from(line));
+                continue;
+            }
+        };
+        let pattern = match path.parent() {
+            Some(parent) => parent.join(pattern),
+            None => PathBuf::from(pattern),
+        };
+        let file_name = pattern
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+        let (prefix, suffix) = match file_name.split_once('*') {
+            Some(parts) => parts,
+            None => {
+                read_ld_so_conf(&pattern, dirs);
+                continue;
+            }
+        };
+        let entries = match pattern.parent().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => continue,
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name().map_or(false, |name| {
+                    let name = name.to_string_lossy();
+                    name.starts_with(prefix) && name.ends_with(suffix)
+                })
+            })
+            .collect();
+        files.sort();
+        for file in files {
+            read_ld_so_conf(&file, dirs);
+        }
+    }
+}
+
+/// How a library is resolved by the runtime loader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Found(PathBuf),
+    Missing,
+    /// Found in several directories. The first one is loaded, which may not be the expected one.
+    Ambiguous(Vec<PathBuf>),
+}
+
+impl Resolution {
+    /// Path which the loader will load
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Resolution::Found(path) => Some(path),
+            Resolution::Ambiguous(paths) => paths.first().map(|path| path.as_path()),
+            Resolution::Missing => None,
+        }
+    }
+}
+
+/// MKL or OpenMP runtime library required by the binary or by another MKL library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeDependency {
+    /// Name in `DT_NEEDED`, or file name for CPU-dispatch libraries, e.g. `libmkl_avx2.so.2`
+    pub name: String,
+    /// Binary or library which requires this library
+    pub required_by: PathBuf,
+    /// CPU-dispatch library loaded by `dlopen` of `libmkl_core` or `libmkl_rt`
+    pub dlopen: bool,
+    pub resolution: Resolution,
+}
+
+/// Result of [check_runtime]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeReport {
+    pub binary: PathBuf,
+    pub dependencies: Vec<RuntimeDependency>,
+}
+
+impl RuntimeReport {
+    /// Missing or ambiguous resolutions
+    ///
+    /// Each CPU-dispatch library is optional since the set shipped with MKL depends on its version,
+    /// but at least one of them must be found for each `libmkl_core` or `libmkl_rt`.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for dep in &self.dependencies {
+            match &dep.resolution {
+                Resolution::Missing if !dep.dlopen => problems.push(format!(
+                    "{} required by {} is not found",
+                    dep.name,
+                    dep.required_by.display()
+                )),
+                Resolution::Ambiguous(paths) => problems.push(format!(
+                    "{} required by {} is found in several directories: {}",
+                    dep.name,
+                    dep.required_by.display(),
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                _ => {}
+            }
+        }
+        let loaders: BTreeSet<&Path> = self
+            .dependencies
+            .iter()
+            .filter(|dep| dep.dlopen)
+            .map(|dep| dep.required_by.as_path())
+            .collect();
+        for loader in loaders {
+            let found = self.dependencies.iter().any(|dep| {
+                dep.dlopen && dep.required_by == loader && dep.resolution != Resolution::Missing
+            });
+            if !found {
+                problems.push(format!(
+                    "No CPU-dispatch library, e.g. libmkl_def.so, is found for {}",
+                    loader.display()
+                ));
+            }
+        }
+        problems
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+impl fmt::Display for RuntimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.binary.display())?;
+        for dep in &self.dependencies {
+            if dep.dlopen && dep.resolution == Resolution::Missing {
+                continue;
+            }
+            let name = if dep.dlopen {
+                format!("{} (dlopen)", dep.name)
+            } else {
+                dep.name.clone()
+            };
+            match &dep.resolution {
+                Resolution::Found(path) => writeln!(f, "  {} => {}", name, path.display())?,
+                Resolution::Missing => writeln!(f, "  {} => not found", name)?,
+                Resolution::Ambiguous(paths) => {
+                    writeln!(f, "  {} => {} (ambiguous)", name, paths[0].display())?;
+                    for path in &paths[1..] {
+                        writeln!(f, "  {}    also {}", " ".repeat(name.len()), path.display())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check MKL and OpenMP runtime of a dynamically linked ELF binary are resolved with current [LoaderEnv]
+///
+/// See [check_runtime_with] for details.
+pub fn check_runtime(binary: &Path) -> Result<RuntimeReport> {
+    check_runtime_with(binary, &LoaderEnv::from_env())
+}
+
+/// Check MKL and OpenMP runtime of a dynamically linked ELF binary are resolved with given [LoaderEnv]
+///
+/// - `DT_NEEDED` entries of MKL (`libmkl_*`) and OpenMP runtimes (`libiomp5`, `libomp`, `libgomp`)
+///   are searched in `DT_RPATH` (only if `DT_RUNPATH` does not exist), `LD_LIBRARY_PATH`, `DT_RUNPATH`,
+///   and system directories in this order, as `ld.so` does. `$ORIGIN` is expanded.
+/// - Found MKL libraries are read recursively in the same manner.
+/// - CPU-dispatch libraries listed in [mkl_dyn_libs], e.g. `libmkl_avx2.so.2`,
+///   are searched as `dlopen` from `libmkl_core` or `libmkl_rt` does.
+///
+/// Other libraries, e.g. `libc.so.6`, are not checked. The ld.so cache is not read,
+/// and thus libraries registered only in the cache are reported as missing.
+pub fn check_runtime_with(binary: &Path, env: &LoaderEnv) -> Result<RuntimeReport> {
+    let dynamic = read_elf_dynamic(binary)?
+        .with_context(|| format!("{} is not an ELF file", binary.display()))?;
+    // `DT_RPATH` of the executable is also used for libraries loaded by others
+    let exe_rpath = if dynamic.runpath.is_empty() {
+        expand_origin(&dynamic.rpath, binary)
+    } else {
+        Vec::new()
+    };
+
+    let mut dependencies = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((binary.to_path_buf(), dynamic));
+    while let Some((object, dynamic)) = queue.pop_front() {
+        let dirs = search_dirs(&object, &dynamic, &exe_rpath, env);
+        for name in &dynamic.needed {
+            if !is_runtime_lib(name) || !visited.insert(name.clone()) {
+                continue;
+            }
+            let resolution = resolve(&dirs, |file_name| file_name == name.as_str());
+            if let Some(path) = resolution.path() {
+                let dynamic = read_elf_dynamic(path)?.unwrap_or_default();
+                queue.push_back((path.to_path_buf(), dynamic));
+            }
+            dependencies.push(RuntimeDependency {
+                name: name.clone(),
+                required_by: object.clone(),
+                dlopen: false,
+                resolution,
+            });
+        }
+    }
+
+    let loaders: Vec<(PathBuf, ElfDynamic)> = dependencies
+        .iter()
+        .filter(|dep| dep.name.starts_with("libmkl_core.") || dep.name.starts_with("libmkl_rt."))
+        .filter_map(|dep| dep.resolution.path())
+        .map(|path| {
+            let dynamic = read_elf_dynamic(path)?.unwrap_or_default();
+            Ok((path.to_path_buf(), dynamic))
+        })
+        .collect::<Result<_>>()?;
+    let cfg = Config {
+        link: LinkType::Dynamic,
+        ..Config::default()
+    };
+    for (loader, dynamic) in loaders {
+        let dirs = search_dirs(&loader, &dynamic, &exe_rpath, env);
+        for name in mkl_dyn_libs(cfg) {
+            let name = name.trim_end_matches("_dll");
+            if name == "mkl_rt" {
+                continue;
+            }
+            // Versioned for oneAPI, e.g. `libmkl_avx2.so.2`, and not for older MKL
+            let stem = format!("lib{}.so", name);
+            let resolution = resolve(&dirs, |file_name| {
+                file_name == stem
+                    || file_name
+                        .strip_prefix(&stem)
+                        .and_then(|rest| rest.strip_prefix('.'))
+                        .map_or(false, |version| {
+                            version.chars().all(|c| c.is_ascii_digit() || c == '.')
+                        })
+            });
+            let name = match resolution.path() {
+                Some(path) => path
+                    .file_name()
+                    .map_or(stem.clone(), |name| name.to_string_lossy().to_string()),
+                None => stem,
+            };
+            dependencies.push(RuntimeDependency {
+                name,
+                required_by: loader.clone(),
+                dlopen: true,
+                resolution,
+            });
+        }
+    }
+
+    Ok(RuntimeReport {
+        binary: binary.to_path_buf(),
+        dependencies,
+    })
+}
+
+/// MKL or OpenMP runtime libraries checked by [check_runtime]
+fn is_runtime_lib(name: &str) -> bool {
+    let name = Path::new(name)
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+    ["libmkl_", "libiomp5.", "libomp.", "libgomp."]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Search directories of `ld.so` for libraries required by `object`
+fn search_dirs(
+    object: &Path,
+    dynamic: &ElfDynamic,
+    exe_rpath: &[PathBuf],
+    env: &LoaderEnv,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if dynamic.runpath.is_empty() {
+        dirs.extend(expand_origin(&dynamic.rpath, object));
+        dirs.extend(exe_rpath.iter().cloned());
+    }
+    dirs.extend(env.ld_library_path.iter().cloned());
+    dirs.extend(expand_origin(&dynamic.runpath, object));
+    dirs.extend(env.system_dirs.iter().cloned());
+
+    let mut unique = Vec::new();
+    for dir in dirs {
+        if !unique.contains(&dir) {
+            unique.push(dir);
+        }
+    }
+    unique
+}
+
+/// Replace `$ORIGIN` or `${ORIGIN}` by the directory containing `object`
+fn expand_origin(dirs: &[String], object: &Path) -> Vec<PathBuf> {
+    let origin = object.parent().unwrap_or_else(|| Path::new("."));
+    let origin = origin.display().to_string();
+    dirs.iter()
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| {
+            PathBuf::from(
+                dir.replace("${ORIGIN}", &origin)
+                    .replace("$ORIGIN", &origin),
+            )
+        })
+        .collect()
+}
+
+/// Find files matching `is_match` in `dirs`
+///
+/// The same file reached through several directories, e.g. by symbolic links, is not ambiguous.
+fn resolve(dirs: &[PathBuf], is_match: impl Fn(&str) -> bool) -> Resolution {
+    let mut found: Vec<PathBuf> = Vec::new();
+    let mut canonical = Vec::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut matched: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_match(&entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        // Prefer the shortest name, e.g. `libmkl_def.so.2` than `libmkl_def.so.2.1`
+        matched.sort_by_key(|path| (path.as_os_str().len(), path.clone()));
+        if let Some(path) = matched.into_iter().next() {
+            let real = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if !canonical.contains(&real) {
+                canonical.push(real);
+                found.push(path);
+            }
+        }
+    }
+    match found.len() {
+        0 => Resolution::Missing,
+        1 => Resolution::Found(found.pop().unwrap()),
+        _ => Resolution::Ambiguous(found),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn check_runtime_resolution() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::OneApi, (2023, 0, 0))?;
+        let binary = mkl.root().join("bin/app");
+        let relative = mkl.library_dir().strip_prefix(mkl.root())?.to_path_buf();
+        write_fake_elf(
+            &binary,
+            &[
+                "libmkl_intel_lp64.so",
+                "libmkl_intel_thread.so",
+                "libmkl_core.so",
+                "libiomp5.so",
+                "libc.so.6",
+            ],
+            &[&format!("$ORIGIN/../{}", relative.display())],
+        )?;
+
+        // Only MKL in RUNPATH is found
+        let mut env = LoaderEnv::default();
+        let report = check_runtime_with(&binary, &env)?;
+        let needed: Vec<&str> = report
+            .dependencies
+            .iter()
+            .filter(|dep| !dep.dlopen)
+            .map(|dep| dep.name.as_str())
+            .collect();
+        assert_eq!(
+            needed,
+            [
+                "libmkl_intel_lp64.so",
+                "libmkl_intel_thread.so",
+                "libmkl_core.so",
+                "libiomp5.so"
+            ]
+        );
+        assert_eq!(
+            report.dependencies[2].resolution,
+            Resolution::Found(
+                mkl.root()
+                    .join("bin/..")
+                    .join(&relative)
+                    .join("libmkl_core.so")
+            )
+        );
+        assert_eq!(report.dependencies[3].resolution, Resolution::Missing);
+        let problems = report.problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("libiomp5.so required by"));
+        assert!(problems[1].starts_with("No CPU-dispatch library"));
+
+        env.ld_library_path = vec![mkl.openmp_dir(), mkl.library_dir()];
+        let report = check_runtime_with(&binary, &env)?;
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.to_string().contains(&format!(
+            "  libmkl_avx2.so (dlopen) => {}/libmkl_avx2.so\n",
+            mkl.library_dir().display()
+        )));
+
+        // Another copy of MKL in LD_LIBRARY_PATH shadows the one in RUNPATH
+        let other = mkl.root().join("other");
+        fs::create_dir_all(&other)?;
+        fs::copy(
+            mkl.library_dir().join("libmkl_core.so"),
+            other.join("libmkl_core.so"),
+        )?;
+        env.ld_library_path.insert(0, other.clone());
+        let report = check_runtime_with(&binary, &env)?;
+        let problems = report.problems();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("libmkl_core.so required by"));
+        assert_eq!(
+            report.dependencies[2].resolution.path(),
+            Some(other.join("libmkl_core.so").as_path())
+        );
+        // CPU-dispatch libraries are searched from libmkl_core in `other`
+        assert!(report.dependencies.iter().any(|dep| dep.dlopen
+            && dep.required_by == other.join("libmkl_core.so")
+            && dep.resolution == Resolution::Found(mkl.library_dir().join("libmkl_def.so"))));
+
+        // Without CPU-dispatch libraries
+        env.ld_library_path.remove(0);
+        for name in mkl_dyn_libs(Config {
+            link: LinkType::Dynamic,
+            ..Config::default()
+        }) {
+            let _ = fs::remove_file(mkl.library_dir().join(format!("lib{}.so", name)));
+        }
+        let problems = check_runtime_with(&binary, &env)?.problems();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("No CPU-dispatch library"));
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! Software bill of materials (SBOM) of found MKL, enabled by `sbom` feature
+
+use crate::{entry::existing_files, mkl_dyn_libs, mkl_file_name, mkl_libs, sha256_file, Library};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// File name of SBOM written by [Library::write_sbom]
+pub const SBOM_FILE_NAME: &str = "mkl.cdx.json";
+
+/// Role of a file in [Library::files]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// MKL library listed in [mkl_libs]
+    Mkl,
+    /// CPU-dispatch library listed in [mkl_dyn_libs]
+    Dispatch,
+    /// OpenMP runtime, e.g. `libiomp5.a`
+    OpenMpRuntime,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileKind::Mkl => write!(f, "mkl"),
+            FileKind::Dispatch => write!(f, "dispatch"),
+            FileKind::OpenMpRuntime => write!(f, "openmp-runtime"),
+        }
+    }
+}
+
+/// A file linked or loaded for the config, see [Library::files]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryFile {
+    pub kind: FileKind,
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// Hex encoded SHA-256 digest
+    pub sha256: String,
+}
+
+impl LibraryFile {
+    fn new(kind: FileKind, path: PathBuf) -> Result<Self> {
+        let size = fs::metadata(&path)
+            .with_context(|| format!("Failed to read metadata of {}", path.display()))?
+            .len();
+        let sha256 = sha256_file(&path)?;
+        Ok(LibraryFile {
+            kind,
+            path,
+            size,
+            sha256,
+        })
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().to_string())
+    }
+}
+
+impl Library {
+    /// Files in [Self::library_files] with their sizes and SHA-256 digests
+    pub fn files(&self) -> Result<Vec<LibraryFile>> {
+        let mut files = Vec::new();
+        for (kind, names) in [
+            (FileKind::Mkl, mkl_libs(self.config)),
+            (FileKind::Dispatch, mkl_dyn_libs(self.config)),
+        ] {
+            for name in names {
+                for path in
+                    existing_files(&self.library_dir, &mkl_file_name(self.config.link, &name))
+                {
+                    files.push(LibraryFile::new(kind, path)?);
+                }
+            }
+        }
+        if let Some((link, dir)) = self.openmp_runtime_dir() {
+            for path in existing_files(dir, &self.openmp_runtime.file_name(link)) {
+                files.push(LibraryFile::new(FileKind::OpenMpRuntime, path)?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// SBOM in [CycloneDX 1.4](https://cyclonedx.org/docs/1.4/json/) JSON format
+    ///
+    /// Each file in [Self::files] is a component of `library` type with SHA-256 hash,
+    /// and its path, size and [FileKind] are recorded as properties `intel-mkl:path`, `intel-mkl:size` and `intel-mkl:kind`.
+    /// MKL itself is described in `metadata.component`, which depends on all files.
+    /// No timestamp is included, and thus the output is reproducible.
+    pub fn to_cyclonedx_json(&self) -> Result<String> {
+        let version = self
+            .version()
+            .ok()
+            .map(|(year, minor, update)| format!("{}.{}.{}", year, minor, update));
+        let files = self.files()?;
+
+        let mut mkl = json!({
+            "type": "library",
+            "bom-ref": "intel-mkl",
+            "supplier": { "name": "Intel Corporation" },
+            "name": "intel-mkl",
+            "description": format!("Intel(R) Math Kernel Library ({})", self.config),
+            "licenses": [
+                { "license": { "name": "Intel Simplified Software License" } }
+            ],
+        });
+        if let Some(version) = &version {
+            mkl["version"] = json!(version);
+        }
+
+        let components: Vec<Value> = files
+            .iter()
+            .map(|file| {
+                let mut component = json!({
+                    "type": "library",
+                    "bom-ref": format!("file:{}", file.path.display()),
+                    "name": file.file_name(),
+                    "hashes": [ { "alg": "SHA-256", "content": file.sha256 } ],
+                    "properties": [
+                        { "name": "intel-mkl:kind", "value": file.kind.to_string() },
+                        { "name": "intel-mkl:path", "value": file.path.display().to_string() },
+                        { "name": "intel-mkl:size", "value": file.size.to_string() },
+                    ],
+                });
+                // OpenMP runtime is distributed with Intel compilers, and its version differs from MKL
+                if let (Some(version), true) = (&version, file.kind != FileKind::OpenMpRuntime) {
+                    component["version"] = json!(version);
+                }
+                component
+            })
+            .collect();
+        let depends_on: Vec<Value> = components
+            .iter()
+            .map(|component| component["bom-ref"].clone())
+            .collect();
+
+        let bom = json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.4",
+            "version": 1,
+            "metadata": {
+                "tools": [
+                    { "name": "intel-mkl-tool", "version": env!("CARGO_PKG_VERSION") }
+                ],
+                "component": mkl,
+            },
+            "components": components,
+            "dependencies": [
+                { "ref": "intel-mkl", "dependsOn": depends_on }
+            ],
+        });
+        Ok(serde_json::to_string_pretty(&bom)?)
+    }
+
+    /// Write [Self::to_cyclonedx_json] into `dir/mkl.cdx.json`, and returns its path
+    pub fn write_sbom(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(SBOM_FILE_NAME);
+        fs::write(&path, self.to_cyclonedx_json()?)
+            .with_context(|| format!("Failed to write SBOM to {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn cyclonedx() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let lib = mkl.seek("mkl-static-lp64-iomp")?;
+
+        let files = lib.files()?;
+        let kinds: Vec<FileKind> = files.iter().map(|file| file.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FileKind::Mkl,
+                FileKind::Mkl,
+                FileKind::Mkl,
+                FileKind::OpenMpRuntime
+            ]
+        );
+        // Stub static library is an empty `ar` archive
+        for file in &files {
+            assert_eq!(file.size, 8);
+            assert_eq!(
+                file.sha256,
+                "f0a17a43c74d2fe5474fa2fd29c8f14799e777d7d75a2cc4d11c20a6e7b161c5"
+            );
+        }
+        assert_eq!(files[3].path, mkl.openmp_dir().join("libiomp5.a"));
+
+        let bom: Value = serde_json::from_str(&lib.to_cyclonedx_json()?)?;
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        assert_eq!(bom["metadata"]["component"]["version"], "2020.0.1");
+        let components = bom["components"].as_array().unwrap();
+        assert_eq!(components.len(), 4);
+        assert_eq!(components[0]["name"], "libmkl_intel_lp64.a");
+        assert_eq!(components[0]["hashes"][0]["content"], files[0].sha256);
+        assert_eq!(components[3]["version"], Value::Null);
+        assert_eq!(
+            bom["dependencies"][0]["dependsOn"]
+                .as_array()
+                .unwrap()
+                .len(),
+            4
+        );
+        Ok(())
+    }
+}
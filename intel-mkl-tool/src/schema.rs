@@ -0,0 +1,187 @@
+//! Serialize/Deserialize implementations enabled by `serde` feature
+
+use crate::{Config, DataModel, Library, LinkType, OpenMpLinkPolicy, OpenMpRuntime, Threading};
+use anyhow::{bail, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{convert::TryFrom, fmt, path::PathBuf, str::FromStr};
+
+/// Version of JSON representation of [Library], see [Library::to_json]
+pub const LIBRARY_SCHEMA_VERSION: u32 = 1;
+
+// These enums are represented by the same string as `Display` and `FromStr`,
+// e.g. `"static"` for `LinkType::Static` and `"mkl-static-lp64-seq"` for `Config`.
+macro_rules! impl_serde_as_str {
+    ($t:ty) => {
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                <$t>::from_str(&s).map_err(de::Error::custom)
+            }
+        }
+    };
+}
+
+impl_serde_as_str!(LinkType);
+impl_serde_as_str!(DataModel);
+impl_serde_as_str!(Threading);
+impl_serde_as_str!(Config);
+impl_serde_as_str!(OpenMpLinkPolicy);
+impl_serde_as_str!(OpenMpRuntime);
+
+#[derive(Serialize, Deserialize)]
+struct LibraryRepr {
+    schema_version: u32,
+    config: Config,
+    include_dir: PathBuf,
+    library_dir: PathBuf,
+    iomp5_static_dir: Option<PathBuf>,
+    iomp5_dynamic_dir: Option<PathBuf>,
+    #[serde(default)]
+    openmp_link_policy: OpenMpLinkPolicy,
+    #[serde(default)]
+    openmp_runtime: OpenMpRuntime,
+}
+
+impl From<&Library> for LibraryRepr {
+    fn from(lib: &Library) -> Self {
+        LibraryRepr {
+            schema_version: LIBRARY_SCHEMA_VERSION,
+            config: lib.config,
+            include_dir: lib.include_dir.clone(),
+            library_dir: lib.library_dir.clone(),
+            iomp5_static_dir: lib.iomp5_static_dir.clone(),
+            iomp5_dynamic_dir: lib.iomp5_dynamic_dir.clone(),
+            openmp_link_policy: lib.openmp_link_policy,
+            openmp_runtime: lib.openmp_runtime,
+        }
+    }
+}
+
+impl TryFrom<LibraryRepr> for Library {
+    type Error = anyhow::Error;
+    fn try_from(repr: LibraryRepr) -> Result<Self> {
+        if repr.schema_version != LIBRARY_SCHEMA_VERSION {
+            bail!(
+                "Unsupported schema version {}, expected {}",
+                repr.schema_version,
+                LIBRARY_SCHEMA_VERSION
+            );
+        }
+        let lib = Library {
+            config: repr.config,
+            include_dir: repr.include_dir,
+            library_dir: repr.library_dir,
+            iomp5_static_dir: repr.iomp5_static_dir,
+            iomp5_dynamic_dir: repr.iomp5_dynamic_dir,
+            openmp_link_policy: repr.openmp_link_policy,
+            openmp_runtime: repr.openmp_runtime,
+        };
+        for warning in lib.check_consistency()? {
+            log::warn!("{}", warning);
+        }
+        Ok(lib)
+    }
+}
+
+impl Serialize for Library {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LibraryRepr::from(self).serialize(serializer)
+    }
+}
+
+/// Deserialized [Library] is validated by [Library::check_consistency]
+/// since files may be changed after serialized.
+impl<'de> Deserialize<'de> for Library {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = LibraryRepr::deserialize(deserializer)?;
+        Library::try_from(repr).map_err(|e| de::Error::custom(DisplayChain(e)))
+    }
+}
+
+/// Display whole chain of `anyhow::Error` in a line
+struct DisplayChain(anyhow::Error);
+
+impl fmt::Display for DisplayChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl Library {
+    /// Serialize into JSON
+    ///
+    /// The schema is versioned by `schema_version` field,
+    /// which is [LIBRARY_SCHEMA_VERSION]:
+    ///
+    /// ```json
+    /// {
+    ///   "schema_version": 1,
+    ///   "config": "mkl-static-lp64-iomp",
+    ///   "include_dir": "/opt/intel/mkl/include",
+    ///   "library_dir": "/opt/intel/mkl/lib/intel64",
+    ///   "iomp5_static_dir": "/opt/intel/lib/intel64",
+    ///   "iomp5_dynamic_dir": null,
+    ///   "openmp_link_policy": "prefer-config",
+    ///   "openmp_runtime": "intel"
+    /// }
+    /// ```
+    ///
+    /// `config` is the same string as the `mkl-*-*-*` feature name.
+    /// `openmp_link_policy` and `openmp_runtime` may be omitted, and then the defaults are used.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserialize from JSON created by [Self::to_json]
+    ///
+    /// This fails if the files recorded in JSON do not exist in the filesystem anymore,
+    /// see [Self::check_consistency].
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VALID_CONFIGS;
+
+    #[test]
+    fn config_as_string() -> Result<()> {
+        for name in VALID_CONFIGS {
+            let cfg = Config::from_str(name)?;
+            let json = serde_json::to_string(&cfg)?;
+            assert_eq!(json, format!("\"{}\"", name));
+            assert_eq!(serde_json::from_str::<Config>(&json)?, cfg);
+        }
+        assert!(serde_json::from_str::<Config>("\"mkl-static-lp64\"").is_err());
+        assert_eq!(serde_json::to_string(&LinkType::Dynamic)?, "\"dynamic\"");
+        assert_eq!(serde_json::to_string(&DataModel::ILP64)?, "\"ilp64\"");
+        assert_eq!(serde_json::to_string(&Threading::OpenMP)?, "\"iomp\"");
+        Ok(())
+    }
+
+    #[test]
+    fn library_revalidated() {
+        let json = r#"{
+            "schema_version": 1,
+            "config": "mkl-static-lp64-seq",
+            "include_dir": "/nonexistent/mkl/include",
+            "library_dir": "/nonexistent/mkl/lib/intel64",
+            "iomp5_static_dir": null,
+            "iomp5_dynamic_dir": null
+        }"#;
+        let err = Library::from_json(json).unwrap_err();
+        assert!(err.to_string().contains("mkl.h is not found"), "{}", err);
+
+        let json = json.replace("\"schema_version\": 1", "\"schema_version\": 2");
+        let err = Library::from_json(&json).unwrap_err();
+        assert!(err.to_string().contains("schema version"), "{}", err);
+    }
+}
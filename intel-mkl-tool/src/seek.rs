@@ -0,0 +1,754 @@
+//! Seek MKL files in a directory tree
+
+use crate::{
+    check_elf_target,
+    entry::{installation_root, missing_mkl_libs, version_in_dir_name, version_in_path},
+    mkl_dyn_libs, mkl_file_name, mkl_libs, Config, Library, LinkType, OpenMpLinkPolicy,
+    OpenMpRuntime, Threading,
+};
+use anyhow::Result;
+use std::{
+    cmp::{Ordering, Reverse},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// Directory names not seeked by default
+///
+/// - `ia32*` and `win-x86` contain libraries for 32-bit systems
+/// - Others contain many files but never MKL libraries
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    "ia32*",
+    "win-x86",
+    "doc",
+    "docs",
+    "documentation",
+    "examples",
+    "benchmarks",
+];
+
+/// Options for [Library::seek_directory_with]
+///
+/// ```
+/// use intel_mkl_tool::SeekOptions;
+///
+/// let opt = SeekOptions::default()
+///     .max_depth(6)
+///     .ignore("licensing");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeekOptions {
+    /// Maximum depth from the root directory. `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Glob patterns of directory names to be skipped. `*` matches any characters.
+    pub ignore_patterns: Vec<String>,
+    /// Seek sub-directories of the root in parallel. This requires `parallel` feature,
+    /// and is ignored without it.
+    pub parallel: bool,
+    /// Follow symbolic links, e.g. `latest` of oneAPI. Links to their ancestors are skipped.
+    pub follow_links: bool,
+    /// Stop walking when all files required for the config are found (default).
+    /// Set false to seek all candidates in the whole tree and choose the best one,
+    /// which may be slow for large directories like `/opt/intel`.
+    pub first_match: bool,
+    /// OpenMP runtimes acceptable for `mkl-*-*-iomp` in the order of preference.
+    /// `None` means [OpenMpRuntime::candidates_from_env], and an empty list means [OpenMpRuntime::Intel].
+    pub openmp_runtimes: Option<Vec<OpenMpRuntime>>,
+}
+
+impl Default for SeekOptions {
+    fn default() -> Self {
+        SeekOptions {
+            max_depth: None,
+            ignore_patterns: DEFAULT_IGNORE_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            parallel: false,
+            follow_links: false,
+            first_match: true,
+            openmp_runtimes: None,
+        }
+    }
+}
+
+impl SeekOptions {
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Add a pattern to [Self::ignore_patterns]
+    pub fn ignore(mut self, pattern: &str) -> Self {
+        self.ignore_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    pub fn follow_links(mut self, follow_links: bool) -> Self {
+        self.follow_links = follow_links;
+        self
+    }
+
+    pub fn first_match(mut self, first_match: bool) -> Self {
+        self.first_match = first_match;
+        self
+    }
+
+    /// Set [Self::openmp_runtimes] instead of reading [crate::ALLOW_LIBOMP_ENV]
+    ///
+    /// An empty list falls back to [OpenMpRuntime::Intel].
+    pub fn openmp_runtimes(mut self, runtimes: Vec<OpenMpRuntime>) -> Self {
+        self.openmp_runtimes = Some(runtimes);
+        self
+    }
+
+    fn is_ignored(&self, dir_name: &OsStr) -> bool {
+        match dir_name.to_str() {
+            Some(name) => self
+                .ignore_patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, name)),
+            None => false,
+        }
+    }
+}
+
+/// Match `name` to `pattern` where `*` matches any (possibly empty) characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((head, rest)) => {
+            let name = match name.strip_prefix(head) {
+                Some(name) => name,
+                None => return false,
+            };
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| glob_match(rest, &name[i..]))
+        }
+    }
+}
+
+/// Candidate directories of MKL files found while walking directories
+#[derive(Debug, Clone)]
+struct Finder {
+    config: Config,
+    policy: OpenMpLinkPolicy,
+    runtimes: Vec<OpenMpRuntime>,
+    include_dirs: Vec<PathBuf>,
+    library_dirs: Vec<PathBuf>,
+    iomp5_static_dirs: Vec<PathBuf>,
+    iomp5_dynamic_dirs: Vec<PathBuf>,
+    libomp_static_dirs: Vec<PathBuf>,
+    libomp_dynamic_dirs: Vec<PathBuf>,
+}
+
+fn push_unique(dirs: &mut Vec<PathBuf>, dir: &Path) {
+    if !dirs.iter().any(|d| d == dir) {
+        dirs.push(dir.to_owned());
+    }
+}
+
+impl Finder {
+    fn new(config: Config, policy: OpenMpLinkPolicy, mut runtimes: Vec<OpenMpRuntime>) -> Self {
+        if runtimes.is_empty() {
+            runtimes.push(OpenMpRuntime::Intel);
+        }
+        Finder {
+            config,
+            policy,
+            runtimes,
+            include_dirs: Vec::new(),
+            library_dirs: Vec::new(),
+            iomp5_static_dirs: Vec::new(),
+            iomp5_dynamic_dirs: Vec::new(),
+            libomp_static_dirs: Vec::new(),
+            libomp_dynamic_dirs: Vec::new(),
+        }
+    }
+
+    fn openmp_dirs(&self, runtime: OpenMpRuntime, link: LinkType) -> &Vec<PathBuf> {
+        match (runtime, link) {
+            (OpenMpRuntime::Intel, LinkType::Static) => &self.iomp5_static_dirs,
+            (OpenMpRuntime::Intel, LinkType::Dynamic) => &self.iomp5_dynamic_dirs,
+            (OpenMpRuntime::Llvm, LinkType::Static) => &self.libomp_static_dirs,
+            (OpenMpRuntime::Llvm, LinkType::Dynamic) => &self.libomp_dynamic_dirs,
+        }
+    }
+
+    fn openmp_dirs_mut(&mut self, runtime: OpenMpRuntime, link: LinkType) -> &mut Vec<PathBuf> {
+        match (runtime, link) {
+            (OpenMpRuntime::Intel, LinkType::Static) => &mut self.iomp5_static_dirs,
+            (OpenMpRuntime::Intel, LinkType::Dynamic) => &mut self.iomp5_dynamic_dirs,
+            (OpenMpRuntime::Llvm, LinkType::Static) => &mut self.libomp_static_dirs,
+            (OpenMpRuntime::Llvm, LinkType::Dynamic) => &mut self.libomp_dynamic_dirs,
+        }
+    }
+
+    /// Any OpenMP runtime acceptable in [Self::runtimes] is found
+    fn has_openmp_runtime(&self) -> bool {
+        self.runtimes.iter().any(|runtime| {
+            [LinkType::Static, LinkType::Dynamic]
+                .iter()
+                .any(|link| !self.openmp_dirs(*runtime, *link).is_empty())
+        })
+    }
+
+    /// All files required for the config are found, and walking can be stopped if [SeekOptions::first_match]
+    fn is_complete(&self) -> bool {
+        let openmp = match self.config.parallel {
+            Threading::Sequential => true,
+            // The most preferred runtime is found
+            Threading::OpenMP => self.runtimes.first().map_or(false, |runtime| {
+                !self
+                    .openmp_dirs(*runtime, self.policy.link_types(self.config)[0])
+                    .is_empty()
+            }),
+        };
+        !self.include_dirs.is_empty() && !self.library_dirs.is_empty() && openmp
+    }
+
+    /// Append candidates found by another walk
+    #[cfg(feature = "parallel")]
+    fn merge(&mut self, other: Finder) {
+        fn extend(a: &mut Vec<PathBuf>, b: Vec<PathBuf>) {
+            for dir in b {
+                push_unique(a, &dir);
+            }
+        }
+        extend(&mut self.include_dirs, other.include_dirs);
+        extend(&mut self.library_dirs, other.library_dirs);
+        extend(&mut self.iomp5_static_dirs, other.iomp5_static_dirs);
+        extend(&mut self.iomp5_dynamic_dirs, other.iomp5_dynamic_dirs);
+        extend(&mut self.libomp_static_dirs, other.libomp_static_dirs);
+        extend(&mut self.libomp_dynamic_dirs, other.libomp_dynamic_dirs);
+    }
+
+    fn visit(&mut self, dir: &Path, file_name: &str) {
+        let config = self.config;
+        if file_name == "mkl.h" {
+            log::info!("Found mkl.h at {}", dir.display());
+            push_unique(&mut self.include_dirs, dir);
+            return;
+        }
+
+        if mkl_libs(config)
+            .iter()
+            .any(|name| file_name == mkl_file_name(config.link, name))
+        {
+            if self.library_dirs.iter().any(|d| d == dir) {
+                return;
+            }
+            if let Err(e) = check_elf_target(&dir.join(file_name)) {
+                log::warn!("Skip {}: {}", file_name, e);
+                return;
+            }
+            let missing = missing_mkl_libs(config, dir);
+            if !missing.is_empty() {
+                log::warn!(
+                    "Skip {} since {} are not found in the same directory {}",
+                    file_name,
+                    missing.join(", "),
+                    dir.display()
+                );
+                return;
+            }
+            log::info!("Found {} at {}", file_name, dir.display());
+            self.library_dirs.push(dir.to_owned());
+            return;
+        }
+
+        // Do not seek OpenMP runtime if `Threading::Sequential`.
+        // Both runtimes are seeked, and `OpenMpLinkPolicy` chooses one of them.
+        if config.parallel == Threading::OpenMP {
+            for runtime in self.runtimes.clone() {
+                for link in [LinkType::Static, LinkType::Dynamic] {
+                    if file_name != runtime.file_name(link) {
+                        continue;
+                    }
+                    if let Err(e) = check_elf_target(&dir.join(file_name)) {
+                        log::warn!("Skip {}: {}", file_name, e);
+                        continue;
+                    }
+                    log::info!(
+                        "Found {} OpenMP runtime ({}): {}",
+                        match link {
+                            LinkType::Static => "static",
+                            LinkType::Dynamic => "dynamic",
+                        },
+                        file_name,
+                        dir.display()
+                    );
+                    push_unique(self.openmp_dirs_mut(runtime, link), dir);
+                }
+            }
+        }
+    }
+
+    /// Walk `root` recursively in the order of [walk_order]
+    fn walk(&mut self, root: &Path, max_depth: Option<usize>, options: &SeekOptions) {
+        let mut walk = walkdir::WalkDir::new(root)
+            .follow_links(options.follow_links)
+            .sort_by(|a, b| walk_order(a.path(), b.path()));
+        if let Some(depth) = max_depth {
+            walk = walk.max_depth(depth);
+        }
+        for entry in walk.into_iter().filter_entry(|entry| {
+            entry.depth() == 0
+                || !(entry.file_type().is_dir() && options.is_ignored(entry.file_name()))
+        }) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // Skip unreadable directories, and symbolic links pointing its ancestor
+                    if let Some(ancestor) = e.loop_ancestor() {
+                        log::info!(
+                            "Skip symbolic link loop to {}: {}",
+                            ancestor.display(),
+                            e.path().map_or("".into(), |p| p.display().to_string())
+                        );
+                    }
+                    continue;
+                }
+            };
+            // `file_type` does not follow symbolic link without `follow_links`, and thus does not call stat
+            let file_type = entry.file_type();
+            if file_type.is_dir() || (file_type.is_symlink() && entry.path().is_dir()) {
+                continue;
+            }
+            let dir = match entry.path().parent() {
+                Some(dir) => dir,
+                None => continue,
+            };
+            if let Some(file_name) = entry.file_name().to_str() {
+                self.visit(dir, file_name);
+                if options.first_match && self.is_complete() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Walk each sub-directory of `root` in parallel, and merge results in the order of their names
+    #[cfg(feature = "parallel")]
+    fn walk_parallel(&mut self, root: &Path, options: &SeekOptions) {
+        use rayon::prelude::*;
+
+        if options.max_depth == Some(0) {
+            return;
+        }
+        let mut entries: Vec<_> = std::fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        entries.sort_by(|a, b| walk_order(&a.path(), &b.path()));
+        let mut sub_dirs = Vec::new();
+        for entry in entries {
+            let path = entry.path();
+            let is_dir = match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => options.follow_links && path.is_dir(),
+                Ok(file_type) => file_type.is_dir(),
+                Err(_) => continue,
+            };
+            if is_dir {
+                if !options.is_ignored(&entry.file_name()) {
+                    sub_dirs.push(path);
+                }
+            } else if let Some(file_name) = entry.file_name().to_str() {
+                self.visit(root, file_name);
+            }
+        }
+        let max_depth = options.max_depth.map(|depth| depth - 1);
+        let found: Vec<Finder> = sub_dirs
+            .par_iter()
+            .map(|dir| {
+                let mut finder = Finder::new(self.config, self.policy, self.runtimes.clone());
+                finder.walk(dir, max_depth, options);
+                finder
+            })
+            .collect();
+        for finder in found {
+            self.merge(finder);
+        }
+    }
+
+    /// Remove candidates which are the same directory reached through symbolic links
+    fn dedup_links(&mut self) {
+        fn dedup(dirs: &mut Vec<PathBuf>) {
+            let mut seen = Vec::new();
+            dirs.retain(|dir| {
+                let real = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+                if seen.contains(&real) {
+                    false
+                } else {
+                    seen.push(real);
+                    true
+                }
+            });
+        }
+        dedup(&mut self.include_dirs);
+        dedup(&mut self.library_dirs);
+        dedup(&mut self.iomp5_static_dirs);
+        dedup(&mut self.iomp5_dynamic_dirs);
+        dedup(&mut self.libomp_static_dirs);
+        dedup(&mut self.libomp_dynamic_dirs);
+    }
+
+    /// Required files in `dir` in addition to [mkl_libs], i.e. OpenMP runtime and dispatch libraries
+    fn missing_in(&self, dir: &Path) -> usize {
+        let config = self.config;
+        let mut files: Vec<String> = mkl_dyn_libs(config)
+            .iter()
+            .map(|name| mkl_file_name(config.link, name))
+            .collect();
+        if config.parallel == Threading::OpenMP {
+            if let Some(runtime) = self.runtimes.first() {
+                files.push(runtime.file_name(self.policy.link_types(config)[0]));
+            }
+        }
+        files
+            .iter()
+            .filter(|file_name| !dir.join(file_name).is_file())
+            .count()
+    }
+
+    /// Library directories sorted from the most preferred one
+    ///
+    /// 1. `intel64` directory, e.g. `lib/intel64` or `lib/intel64_lin`
+    /// 2. Directory containing all required libraries, see [Self::missing_in]
+    /// 3. Newer version, see [version_in_path]
+    /// 4. Path in lexicographical order
+    fn ranked_library_dirs(&self) -> Vec<PathBuf> {
+        let mut ranked: Vec<_> = self
+            .library_dirs
+            .iter()
+            .map(|dir| {
+                let intel64 = dir.components().any(|c| {
+                    c.as_os_str()
+                        .to_str()
+                        .map_or(false, |c| c.starts_with("intel64"))
+                });
+                (
+                    !intel64,
+                    self.missing_in(dir),
+                    Reverse(version_in_path(dir).map(|v| parse_version(&v))),
+                    dir.clone(),
+                )
+            })
+            .collect();
+        ranked.sort();
+        ranked.into_iter().map(|(.., dir)| dir).collect()
+    }
+
+    /// Candidate in `dirs` most suitable for `library_dir`
+    ///
+    /// Prefer `library_dir` itself, then a directory in the same installation root
+    /// or of the same version, and then the first one in path order.
+    fn nearest(dirs: &[PathBuf], library_dir: &Path, root_names: &[&str]) -> Option<PathBuf> {
+        let root = installation_root(library_dir, &["lib"]);
+        let version = version_in_path(library_dir);
+        dirs.iter()
+            .min_by_key(|dir| {
+                (
+                    dir.as_path() != library_dir,
+                    root.is_none() || installation_root(dir, root_names) != root,
+                    version.is_none() || version_in_path(dir) != version,
+                    dir.to_path_buf(),
+                )
+            })
+            .cloned()
+    }
+
+    fn into_library(mut self, root_dir: &Path) -> Option<Library> {
+        let config = self.config;
+        self.dedup_links();
+        if config.parallel == Threading::OpenMP && !self.has_openmp_runtime() {
+            if let Some(lib) = self.library_dirs.first() {
+                log::warn!(
+                    "OpenMP runtime not found while MKL found at {}",
+                    lib.display()
+                );
+            }
+            return None;
+        }
+        let library_dirs = self.ranked_library_dirs();
+        // `libomp` is used only if `libiomp5` is not usable for all candidates
+        let runtimes = match config.parallel {
+            Threading::OpenMP => self.runtimes.clone(),
+            Threading::Sequential => vec![OpenMpRuntime::default()],
+        };
+        for runtime in runtimes {
+            for library_dir in &library_dirs {
+                let include_dir = Self::nearest(&self.include_dirs, library_dir, &["include"])?;
+                let lib = Library {
+                    config,
+                    include_dir,
+                    library_dir: library_dir.clone(),
+                    iomp5_static_dir: Self::nearest(
+                        self.openmp_dirs(runtime, LinkType::Static),
+                        library_dir,
+                        &["lib"],
+                    ),
+                    iomp5_dynamic_dir: Self::nearest(
+                        self.openmp_dirs(runtime, LinkType::Dynamic),
+                        library_dir,
+                        &["lib"],
+                    ),
+                    openmp_link_policy: self.policy,
+                    openmp_runtime: runtime,
+                };
+                match lib.check_consistency() {
+                    Ok(warnings) => {
+                        for warning in warnings {
+                            log::warn!("{}", warning);
+                        }
+                        if library_dirs.len() > 1 {
+                            log::info!(
+                                "Choose {} among {} candidates",
+                                library_dir.display(),
+                                library_dirs.len()
+                            );
+                        }
+                        if runtime == OpenMpRuntime::Llvm {
+                            log::warn!(
+                                "Use LLVM OpenMP runtime instead of Intel OpenMP runtime for {}",
+                                config
+                            );
+                        }
+                        return Some(lib);
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "Inconsistent MKL installation under {}: {}",
+                            root_dir.display(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Numeric components of a version string, e.g. `[2020, 1, 217]` for `2020.1.217`
+/// Order of entries in the same directory to be walked
+///
+/// Versioned directories of MKL, e.g. `mkl/2023.0.0`, come first from the newest one,
+/// so that walking stops at the newest installation by [SeekOptions::first_match].
+/// Others follow in the order of file names.
+fn walk_order(a: &Path, b: &Path) -> Ordering {
+    let key = |path: &Path| {
+        let parent = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|name| name.to_str());
+        let version = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| version_in_dir_name(parent, name))
+            .map(|version| parse_version(&version));
+        (version.is_none(), Reverse(version))
+    };
+    key(a)
+        .cmp(&key(b))
+        .then_with(|| a.file_name().cmp(&b.file_name()))
+}
+
+fn parse_version(version: &str) -> Vec<u32> {
+    version.split('.').flat_map(|v| v.parse().ok()).collect()
+}
+
+impl Library {
+    /// Seek MKL libraries in the given directory with options
+    ///
+    /// Directories are walked in the order of file names except that versioned directories of MKL,
+    /// e.g. `mkl/2023.0.0` and `mkl/2021.1.1`, are walked from the newest one,
+    /// and walking stops when all files required for the config are found unless [SeekOptions::first_match] is false. When several directories found contain MKL,
+    /// they are ranked in the following order, and the first consistent one is used:
+    ///
+    /// 1. `intel64` directory, e.g. `lib/intel64` or `lib/intel64_lin`
+    /// 2. Directory containing all required libraries,
+    ///    i.e. OpenMP runtime and dispatch libraries in addition to [mkl_libs]
+    /// 3. Newer version embedded in the path, e.g. `2023.0.0` in `mkl/2023.0.0/lib/intel64`
+    /// 4. Path in lexicographical order
+    ///
+    /// `mkl.h` and OpenMP runtime are chosen from the directories nearest to the library directory.
+    /// See [Library::seek_directory] for detail.
+    pub fn seek_directory_with(
+        config: Config,
+        root_dir: impl AsRef<Path>,
+        options: &SeekOptions,
+    ) -> Result<Option<Self>> {
+        let root_dir = root_dir.as_ref();
+        if !root_dir.is_dir() {
+            return Ok(None);
+        }
+        let runtimes = options
+            .openmp_runtimes
+            .clone()
+            .unwrap_or_else(OpenMpRuntime::candidates_from_env);
+        let mut finder = Finder::new(config, OpenMpLinkPolicy::from_env()?, runtimes);
+        if options.parallel && cfg!(feature = "parallel") {
+            #[cfg(feature = "parallel")]
+            finder.walk_parallel(root_dir, options);
+        } else {
+            finder.walk(root_dir, options.max_depth, options);
+        }
+        Ok(finder.into_library(root_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("ia32*", "ia32"));
+        assert!(glob_match("ia32*", "ia32_lin"));
+        assert!(!glob_match("ia32*", "intel64"));
+        assert!(glob_match("win-x86", "win-x86"));
+        assert!(!glob_match("win-x86", "win-x86_64"));
+        assert!(glob_match("*doc*", "documentation"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("doc", "docs"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn seek_with_options() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let cfg = Config::from_str("mkl-static-lp64-seq")?;
+        let found = Library::seek_directory(cfg, mkl.root())?.unwrap();
+        assert_eq!(found.library_dir, mkl.library_dir());
+
+        let ignore_mkl = SeekOptions::default().ignore("mkl");
+        assert_eq!(
+            Library::seek_directory_with(cfg, mkl.root(), &ignore_mkl)?,
+            None
+        );
+        // `opt/intel/mkl/lib/intel64` is at depth 5
+        let shallow = SeekOptions::default().max_depth(4);
+        assert_eq!(
+            Library::seek_directory_with(cfg, mkl.root(), &shallow)?,
+            None
+        );
+        let parallel = SeekOptions::default().parallel(true);
+        assert_eq!(
+            Library::seek_directory_with(cfg, mkl.root(), &parallel)?,
+            Some(found)
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn empty_openmp_runtimes() -> Result<()> {
+        let mkl = FakeMkl::new(Layout::Classic, (2020, 0, 1))?;
+        let cfg = Config::from_str("mkl-static-lp64-iomp")?;
+        let runtimes = SeekOptions::default().openmp_runtimes(Vec::new());
+        for options in [runtimes.clone(), runtimes.first_match(false)] {
+            let lib = Library::seek_directory_with(cfg, mkl.root(), &options)?.unwrap();
+            assert_eq!(lib.openmp_runtime, OpenMpRuntime::Intel);
+            assert_eq!(lib.iomp5_static_dir, Some(mkl.openmp_dir()));
+        }
+        let lib = Library::from_dirs_with(
+            cfg,
+            mkl.include_dir(),
+            mkl.library_dir(),
+            &[mkl.openmp_dir()],
+            &[],
+        )?
+        .unwrap();
+        assert_eq!(lib.openmp_runtime, OpenMpRuntime::Intel);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn libomp() -> Result<()> {
+        // Replace `libiomp5` by `libomp` as LLVM OpenMP runtime installed by system package manager
+        let mkl = FakeMkl::new(Layout::Conda, (2023, 1, 0))?;
+        for link in [LinkType::Static, LinkType::Dynamic] {
+            std::fs::remove_file(mkl.openmp_dir().join(OpenMpRuntime::Intel.file_name(link)))?;
+        }
+        std::fs::write(mkl.openmp_dir().join("libomp.so"), "")?;
+        let cfg = Config::from_str("mkl-static-lp64-iomp")?;
+
+        assert_eq!(Library::seek_directory(cfg, mkl.root())?, None);
+
+        // Same as `INTEL_MKL_ALLOW_LIBOMP=1`
+        let runtimes = vec![OpenMpRuntime::Intel, OpenMpRuntime::Llvm];
+        let options = SeekOptions::default().openmp_runtimes(runtimes.clone());
+        let lib = Library::seek_directory_with(cfg, mkl.root(), &options)?;
+        let from_dirs =
+            Library::from_dirs_with(cfg, mkl.include_dir(), mkl.library_dir(), &[], &runtimes)?;
+
+        for lib in [lib.unwrap(), from_dirs.unwrap()] {
+            assert_eq!(lib.openmp_runtime, OpenMpRuntime::Llvm);
+            assert_eq!(lib.openmp_link_type(), Some(LinkType::Dynamic));
+            assert_eq!(
+                lib.cargo_metadata().last().unwrap(),
+                "cargo:rustc-link-lib=dylib=omp"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rank_candidates() -> Result<()> {
+        let old = FakeMkl::new(Layout::OneApi, (2021, 1, 1))?;
+        let new = FakeMkl::new_in(old.root(), Layout::OneApi, (2023, 0, 0))?;
+        let cfg = Config::from_str("mkl-static-lp64-seq")?;
+
+        // Walking stops at `2023.0.0` walked before `2021.1.1` by default
+        let first = Library::seek_directory(cfg, old.root())?.unwrap();
+        assert_eq!(first.library_dir, new.library_dir());
+        assert_eq!(first.include_dir, new.include_dir());
+        let iomp = Config::from_str("mkl-static-lp64-iomp")?;
+        let first = Library::seek_directory(iomp, old.root())?.unwrap();
+        assert_eq!(first.library_dir, new.library_dir());
+        assert_eq!(first.iomp5_static_dir, Some(new.openmp_dir()));
+
+        let all = SeekOptions::default().first_match(false);
+        let found = Library::seek_directory_with(cfg, old.root(), &all)?.unwrap();
+        assert_eq!(found.library_dir, new.library_dir());
+        assert_eq!(found.include_dir, new.include_dir());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_symlinks() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let mkl = FakeMkl::new(Layout::OneApi, (2023, 0, 0))?;
+        let links = mkl.root().join("links");
+        std::fs::create_dir_all(&links)?;
+        symlink(
+            mkl.root().join("opt/intel/oneapi/mkl/2023.0.0"),
+            links.join("latest"),
+        )?;
+        symlink(&links, links.join("loop"))?;
+
+        let cfg = Config::from_str("mkl-static-lp64-seq")?;
+        assert_eq!(Library::seek_directory(cfg, &links)?, None);
+        let follow =
+            Library::seek_directory_with(cfg, &links, &SeekOptions::default().follow_links(true))?;
+        assert_eq!(
+            follow.unwrap().library_dir,
+            links.join("latest/lib/intel64")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+//! Fake MKL installations for testing, enabled by `testing` feature
+//!
+//! [FakeMkl] creates a directory tree in the temporary directory
+//! which mimics a MKL installation of the given [Layout].
+//! Libraries are stub files, i.e. empty `ar` archives for static libraries and empty files for shared libraries,
+//! and thus they can be seeked but cannot be linked.
+//!
+//! ```
+//! use intel_mkl_tool::testing::*;
+//!
+//! let mkl = FakeMkl::new(Layout::OneApi, (2023, 0, 0)).unwrap();
+//! let lib = mkl.seek("mkl-static-lp64-seq").unwrap();
+//! assert_eq!(lib.library_dir, mkl.library_dir());
+//! assert_eq!(lib.version().unwrap(), (2023, 0, 0));
+//! ```
+
+use crate::{mkl_dyn_libs, mkl_libs, Config, Library, LinkType};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Directory layouts of MKL distributions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Parallel Studio XE, i.e. `/opt/intel/mkl/{include,lib/intel64}`
+    /// with OpenMP runtime in `/opt/intel/lib/intel64`
+    Classic,
+    /// oneAPI, i.e. `/opt/intel/oneapi/mkl/{version}/{include,lib/intel64}`
+    /// with OpenMP runtime in `/opt/intel/oneapi/compiler/{version}/linux/compiler/lib/intel64_lin`
+    OneApi,
+    /// conda environment with `mkl-include`, `mkl-devel` and `intel-openmp`, i.e. `{prefix}/{include,lib}`
+    Conda,
+    /// Unpacked pip wheels of `mkl-include` and `mkl-devel`,
+    /// i.e. `mkl_include-{version}.data/data/include` and `mkl_devel-{version}.data/data/lib`
+    PipWheel,
+    /// Debian/Ubuntu `intel-mkl` package, i.e. `/usr/include/mkl` and `/usr/lib/{arch}-linux-gnu`
+    Multiarch,
+    /// Windows installation with Windows file names, e.g. `mkl_core.lib` and `mkl_core_dll.lib`,
+    /// in `IntelSWTools/compilers_and_libraries_{version}/windows/mkl/{include,lib/intel64_win}`
+    Windows,
+}
+
+impl Layout {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Layout::Classic,
+            Layout::OneApi,
+            Layout::Conda,
+            Layout::PipWheel,
+            Layout::Multiarch,
+            Layout::Windows,
+        ]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Layout::Classic => "classic",
+            Layout::OneApi => "oneapi",
+            Layout::Conda => "conda",
+            Layout::PipWheel => "pip",
+            Layout::Multiarch => "multiarch",
+            Layout::Windows => "windows",
+        }
+    }
+}
+
+/// Fake MKL installation in a temporary directory, removed when dropped
+#[derive(Debug)]
+pub struct FakeMkl {
+    root: PathBuf,
+    layout: Layout,
+    version: (u32, u32, u32),
+    /// Remove `root` when dropped, false for [FakeMkl::new_in]
+    owned: bool,
+}
+
+impl Drop for FakeMkl {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+impl FakeMkl {
+    /// Create all files of every [Config] for `version`, e.g. `(2023, 0, 0)`
+    pub fn new(layout: Layout, version: (u32, u32, u32)) -> Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "intel-mkl-tool-fake-{}-{}-{}",
+            layout.name(),
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+        let mkl = FakeMkl {
+            root,
+            layout,
+            version,
+            owned: true,
+        };
+        mkl.create()?;
+        Ok(mkl)
+    }
+
+    /// Create another installation in `root`, e.g. [FakeMkl::root] of another one,
+    /// to put several versions side by side. `root` is not removed when dropped.
+    pub fn new_in(root: &Path, layout: Layout, version: (u32, u32, u32)) -> Result<Self> {
+        let mkl = FakeMkl {
+            root: root.to_owned(),
+            layout,
+            version,
+            owned: false,
+        };
+        mkl.create()?;
+        Ok(mkl)
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn version(&self) -> (u32, u32, u32) {
+        self.version
+    }
+
+    /// Directory to be passed to [crate::Library::seek_directory]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Seek `config`, e.g. `"mkl-static-lp64-seq"`, in [Self::root] by [crate::Library::seek_directory]
+    ///
+    /// This fails if it is not found.
+    pub fn seek(&self, config: &str) -> Result<Library> {
+        let config = Config::from_str(config)?;
+        Library::seek_directory(config, &self.root)?
+            .with_context(|| format!("{} is not found in {}", config, self.root.display()))
+    }
+
+    fn version_str(&self) -> String {
+        let (year, minor, update) = self.version;
+        format!("{}.{}.{}", year, minor, update)
+    }
+
+    /// Directory containing `mkl.h`
+    pub fn include_dir(&self) -> PathBuf {
+        let v = self.version_str();
+        match self.layout {
+            Layout::Classic => self.root.join("opt/intel/mkl/include"),
+            Layout::OneApi => self
+                .root
+                .join("opt/intel/oneapi/mkl")
+                .join(v)
+                .join("include"),
+            Layout::Conda => self.root.join("envs/mkl/include"),
+            Layout::PipWheel => self
+                .root
+                .join(format!("mkl_include-{}.data/data/include", v)),
+            Layout::Multiarch => self.root.join("usr/include/mkl"),
+            Layout::Windows => self.windows_root().join("mkl/include"),
+        }
+    }
+
+    /// Directory containing MKL libraries
+    pub fn library_dir(&self) -> PathBuf {
+        let v = self.version_str();
+        match self.layout {
+            Layout::Classic => self.root.join("opt/intel/mkl/lib/intel64"),
+            Layout::OneApi => self
+                .root
+                .join("opt/intel/oneapi/mkl")
+                .join(v)
+                .join("lib/intel64"),
+            Layout::Conda => self.root.join("envs/mkl/lib"),
+            Layout::PipWheel => self.root.join(format!("mkl_devel-{}.data/data/lib", v)),
+            Layout::Multiarch => self
+                .root
+                .join("usr/lib")
+                .join(format!("{}-linux-gnu", std::env::consts::ARCH)),
+            Layout::Windows => self.windows_root().join("mkl/lib/intel64_win"),
+        }
+    }
+
+    /// Directory containing OpenMP runtime
+    pub fn openmp_dir(&self) -> PathBuf {
+        match self.layout {
+            Layout::Classic => self.root.join("opt/intel/lib/intel64"),
+            Layout::OneApi => self
+                .root
+                .join("opt/intel/oneapi/compiler")
+                .join(self.version_str())
+                .join("linux/compiler/lib/intel64_lin"),
+            Layout::Windows => self.windows_root().join("compiler/lib/intel64_win"),
+            Layout::Conda | Layout::PipWheel | Layout::Multiarch => self.library_dir(),
+        }
+    }
+
+    fn windows_root(&self) -> PathBuf {
+        self.root
+            .join("IntelSWTools")
+            .join(format!("compilers_and_libraries_{}", self.version_str()))
+            .join("windows")
+    }
+
+    fn create(&self) -> Result<()> {
+        let include_dir = self.include_dir();
+        fs::create_dir_all(&include_dir)?;
+        fs::write(include_dir.join("mkl.h"), "#include \"mkl_version.h\"\n")?;
+        let (year, minor, update) = self.version;
+        fs::write(
+            include_dir.join("mkl_version.h"),
+            format!(
+                "#define __INTEL_MKL__ {}\n#define __INTEL_MKL_MINOR__ {}\n#define __INTEL_MKL_UPDATE__ {}\n",
+                year, minor, update
+            ),
+        )?;
+
+        let windows = self.layout == Layout::Windows;
+        let library_dir = self.library_dir();
+        fs::create_dir_all(&library_dir)?;
+        for file_name in library_file_names(windows) {
+            stub_library(&library_dir.join(file_name))?;
+        }
+        let openmp_dir = self.openmp_dir();
+        fs::create_dir_all(&openmp_dir)?;
+        for link in [LinkType::Static, LinkType::Dynamic] {
+            stub_library(&openmp_dir.join(openmp_file_name(windows, link)))?;
+        }
+
+        // Libraries for 32-bit systems which must not be used
+        match self.layout {
+            Layout::Classic => {
+                let ia32 = self.root.join("opt/intel/mkl/lib/ia32");
+                fs::create_dir_all(&ia32)?;
+                for file_name in library_file_names(false) {
+                    stub_library(&ia32.join(file_name))?;
+                }
+            }
+            Layout::Windows => {
+                let ia32 = self.windows_root().join("mkl/lib/ia32_win");
+                fs::create_dir_all(&ia32)?;
+                for file_name in library_file_names(true) {
+                    stub_library(&ia32.join(file_name))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Empty `ar` archive for static libraries, and empty file for others
+fn stub_library(path: &Path) -> Result<()> {
+    let is_static = path
+        .extension()
+        .map_or(false, |ext| ext == "a" || ext == "lib");
+    fs::write(path, if is_static { "!<arch>\n" } else { "" })?;
+    Ok(())
+}
+
+/// Write a minimal ELF64 little-endian file which only has a dynamic section
+///
+/// This is enough for [crate::read_elf_dynamic] and [crate::check_runtime],
+/// while it cannot be executed or loaded.
+pub fn write_fake_elf(path: &Path, needed: &[&str], runpath: &[&str]) -> Result<()> {
+    // `.dynstr` starts with an empty string
+    let mut strtab = vec![0_u8];
+    let mut dynamic = Vec::new();
+    let mut push_string = |tag: u64, value: &str| {
+        dynamic.push((tag, strtab.len() as u64));
+        strtab.extend_from_slice(value.as_bytes());
+        strtab.push(0);
+    };
+    for name in needed {
+        push_string(1 /* DT_NEEDED */, name);
+    }
+    if !runpath.is_empty() {
+        push_string(29 /* DT_RUNPATH */, &runpath.join(":"));
+    }
+    dynamic.push((0 /* DT_NULL */, 0));
+
+    let strtab_offset = 64_u64;
+    let dynamic_offset = (strtab_offset + strtab.len() as u64 + 7) / 8 * 8;
+    let shoff = dynamic_offset + 16 * dynamic.len() as u64;
+
+    let mut buf = vec![0_u8; 64];
+    buf[0..4].copy_from_slice(b"\x7fELF");
+    buf[4] = 2; // ELFCLASS64
+    buf[5] = 1; // ELFDATA2LSB
+    buf[6] = 1; // EV_CURRENT
+    buf[16..18].copy_from_slice(&3_u16.to_le_bytes()); // ET_DYN
+    buf[18..20].copy_from_slice(&62_u16.to_le_bytes()); // EM_X86_64
+    buf[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+    buf[0x34..0x36].copy_from_slice(&64_u16.to_le_bytes()); // e_ehsize
+    buf[0x3a..0x3c].copy_from_slice(&64_u16.to_le_bytes()); // e_shentsize
+    buf[0x3c..0x3e].copy_from_slice(&3_u16.to_le_bytes()); // e_shnum
+    buf.extend_from_slice(&strtab);
+    buf.resize(dynamic_offset as usize, 0);
+    for (tag, value) in &dynamic {
+        buf.extend_from_slice(&tag.to_le_bytes());
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Section headers: null, `.dynstr`, and `.dynamic` linked to `.dynstr`
+    let section = |sh_type: u32, offset: u64, size: u64, link: u32| {
+        let mut header = vec![0_u8; 64];
+        header[4..8].copy_from_slice(&sh_type.to_le_bytes());
+        header[24..32].copy_from_slice(&offset.to_le_bytes());
+        header[32..40].copy_from_slice(&size.to_le_bytes());
+        header[40..44].copy_from_slice(&link.to_le_bytes());
+        header
+    };
+    buf.extend(section(0, 0, 0, 0));
+    buf.extend(section(3, strtab_offset, strtab.len() as u64, 0));
+    buf.extend(section(6, dynamic_offset, 16 * dynamic.len() as u64, 1));
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, buf)?;
+    Ok(())
+}
+
+/// File names of all MKL libraries for every [Config]
+///
+/// This does not depend on the host OS unlike [crate::mkl_file_name],
+/// i.e. Windows names if `windows` is true, Linux names otherwise.
+fn library_file_names(windows: bool) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for cfg in Config::possibles() {
+        for name in mkl_libs(cfg).into_iter().chain(mkl_dyn_libs(cfg)) {
+            let name = name.trim_end_matches("_dll");
+            names.insert(match (windows, cfg.link) {
+                (true, LinkType::Static) => format!("{}.lib", name),
+                (true, LinkType::Dynamic) => format!("{}_dll.lib", name),
+                (false, LinkType::Static) => format!("lib{}.a", name),
+                (false, LinkType::Dynamic) => format!("lib{}.so", name),
+            });
+        }
+    }
+    names
+}
+
+fn openmp_file_name(windows: bool, link: LinkType) -> &'static str {
+    match (windows, link) {
+        (true, LinkType::Static) => "libiomp5md.lib",
+        (true, LinkType::Dynamic) => "libiomp5md.dll",
+        (false, LinkType::Static) => "libiomp5.a",
+        (false, LinkType::Dynamic) => "libiomp5.so",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    /// Layouts whose file names match to the host
+    fn host_layouts() -> Vec<Layout> {
+        Layout::all()
+            .into_iter()
+            .filter(|layout| (*layout == Layout::Windows) == cfg!(target_os = "windows"))
+            .collect()
+    }
+
+    #[test]
+    fn seek_all_layouts() -> Result<()> {
+        if !cfg!(any(target_os = "linux", target_os = "windows")) {
+            return Ok(());
+        }
+        for layout in host_layouts() {
+            let mkl = FakeMkl::new(layout, (2020, 0, 1))?;
+            for cfg in Config::possibles() {
+                let lib = Library::seek_directory(cfg, mkl.root())?
+                    .unwrap_or_else(|| panic!("{} is not found in {:?} layout", cfg, layout));
+                assert_eq!(lib.include_dir, mkl.include_dir(), "{:?}", layout);
+                assert_eq!(lib.library_dir, mkl.library_dir(), "{:?}", layout);
+                assert_eq!(lib.version()?, (2020, 0, 1));
+                if cfg.parallel == Threading::OpenMP {
+                    let openmp = match cfg.link {
+                        LinkType::Static => &lib.iomp5_static_dir,
+                        LinkType::Dynamic => &lib.iomp5_dynamic_dir,
+                    };
+                    assert_eq!(openmp.as_deref(), Some(mkl.openmp_dir().as_path()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn windows_names_on_other_os() -> Result<()> {
+        if cfg!(target_os = "windows") {
+            return Ok(());
+        }
+        let mkl = FakeMkl::new(Layout::Windows, (2022, 0, 0))?;
+        for cfg in Config::possibles() {
+            assert_eq!(Library::seek_directory(cfg, mkl.root())?, None);
+        }
+        Ok(())
+    }
+}